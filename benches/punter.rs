@@ -0,0 +1,272 @@
+// Setup, move processing, scoring and search speed on maps of increasing
+// size: the bundled maps, then a 100k-site grid whose site ids are huge and
+// scattered, the way some servers hand them out. Then self-play between
+// search variants and the default search on the bundled maps. Results go to
+// stdout and are appended to bench_output.txt under the commit they were
+// taken at, so runs can be compared across commits.
+//
+// Pass "speed" or "selfplay" to run only one of the two.
+extern crate punter;
+#[macro_use]
+extern crate serde_json;
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use punter::arena;
+use punter::player::Player;
+use punter::protocol::{self, Move, Score};
+use punter::punter::{Diagnostics, Input, InputMap, Punter, PunterType, SearchConfig};
+
+const MAPS: &[(&str, &str)] = &[
+    ("sample", include_str!("maps/sample.json")),
+    ("medium", include_str!("maps/medium.json")),
+    ("large", include_str!("maps/large.json")),
+];
+
+const SIDE: usize = 317;
+const GRID_MINES: usize = 8;
+const PUNTERS: usize = 4;
+// Claims made before measuring anything that depends on the game so far:
+// half the rivers, up to this many
+const MAX_CLAIMS: usize = 10000;
+// Stride through the rivers when picking claims; prime, so nobody claims a
+// river twice unless the map has a multiple of it
+const STRIDE: usize = 7919;
+const SEARCH_MS: u64 = 1000;
+const RUNS: usize = 5;
+// Self-play games per bundled map, seats alternating, and the time each
+// move gets. None on the large map: at this pace the search there stays
+// under the iterations it needs to be trusted, so both sides play greedy
+// and every game is a mirror match.
+const SELFPLAY_GAMES: &[usize] = &[100, 40];
+const SELFPLAY_MS: u64 = 50;
+
+// A bijection on u64, so ids stay unique but look random
+fn site_id(idx: usize) -> usize {
+    (idx as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) as usize ^ (1 << 48)
+}
+
+fn grid_map() -> serde_json::Value {
+    let mut sites = vec![];
+    let mut rivers = vec![];
+    for row in 0..SIDE {
+        for col in 0..SIDE {
+            let idx = row * SIDE + col;
+            sites.push(json!({"id": site_id(idx)}));
+            if col + 1 < SIDE {
+                rivers.push(json!({"source": site_id(idx), "target": site_id(idx + 1)}));
+            }
+            if row + 1 < SIDE {
+                rivers.push(json!({"source": site_id(idx), "target": site_id(idx + SIDE)}));
+            }
+        }
+    }
+    let mines = (0..GRID_MINES)
+        .map(|m| site_id(m * SIDE * SIDE / GRID_MINES + SIDE / 2))
+        .collect::<Vec<_>>();
+    json!({"sites": sites, "rivers": rivers, "mines": mines})
+}
+
+// Claims spread all over the map, in rounds of one per punter
+fn claims(map: &serde_json::Value) -> Vec<Vec<protocol::Move>> {
+    let rivers = map["rivers"].as_array().unwrap();
+    assert!(!rivers.len().is_multiple_of(STRIDE));
+    let claims = (rivers.len() / 2).min(MAX_CLAIMS);
+    (0..claims / PUNTERS).map(|round| {
+        (0..PUNTERS).map(|p| {
+            let river = &rivers[(round * PUNTERS + p) * STRIDE % rivers.len()];
+            protocol::Move::claim (protocol::Claim {
+                punter: p,
+                source: river["source"].as_u64().unwrap() as usize,
+                target: river["target"].as_u64().unwrap() as usize,
+            })
+        }).collect()
+    }).collect()
+}
+
+struct Report {
+    lines: Vec<String>,
+}
+
+impl Report {
+    fn line(&mut self, line: String) {
+        println!("{}", line);
+        self.lines.push(line);
+    }
+
+    // Best and median of a few runs, since a shared machine only ever adds
+    // time
+    fn time<F>(&mut self, map: &str, name: &str, ops: usize, mut run: F)
+        where F: FnMut() -> Duration
+    {
+        let mut runs = (0..RUNS).map(|_| run()).collect::<Vec<_>>();
+        runs.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1e3;
+        let (best, median) = (ms(runs[0]), ms(runs[RUNS / 2]));
+        self.line(format!("{:<8} {:<16} {:>10.2} ms {:>10.2} ms median {:>10.2} us/op",
+                          map, name, best, median, best * 1e3 / ops.max(1) as f64));
+    }
+
+    fn save(&self) {
+        let commit = Command::new("git")
+            .args(["describe", "--always", "--dirty"])
+            .output()
+            .ok()
+            .and_then(|out| String::from_utf8(out.stdout).ok())
+            .map_or("unknown".to_string(), |commit| commit.trim().to_string());
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/bench_output.txt");
+        let mut file = OpenOptions::new().create(true).append(true).open(path).unwrap();
+        writeln!(file, "== {}", commit).unwrap();
+        for line in &self.lines {
+            writeln!(file, "{}", line).unwrap();
+        }
+        println!("Appended to {}", path);
+    }
+}
+
+fn bench(report: &mut Report, name: &str, map: serde_json::Value) {
+    let input: Input = serde_json::from_value(json!({
+        "punter": 0,
+        "punters": PUNTERS,
+        "map": map,
+    })).unwrap();
+    let turns = claims(&map);
+    report.line(format!("{:<8} {} sites, {} rivers, {} mines, {} claims",
+                        name, map["sites"].as_array().unwrap().len(),
+                        map["rivers"].as_array().unwrap().len(),
+                        map["mines"].as_array().unwrap().len(), turns.len() * PUNTERS));
+
+    report.time(name, "setup", 1, || {
+        let input = input.clone();
+        let start = Instant::now();
+        Punter::new(input, PunterType::Random);
+        start.elapsed()
+    });
+
+    report.time(name, "process_turn", turns.len(), || {
+        let mut punter = Punter::new(input.clone(), PunterType::Random);
+        let start = Instant::now();
+        for moves in &turns {
+            punter.process_turn(moves);
+        }
+        start.elapsed()
+    });
+
+    let mut punter = Punter::new(input.clone(), PunterType::MCTS);
+    for moves in &turns {
+        punter.process_turn(moves);
+    }
+    report.time(name, "compute_scores", 1, || {
+        let start = Instant::now();
+        assert_eq!(punter.scores().len(), PUNTERS);
+        start.elapsed()
+    });
+
+    report.time(name, "state roundtrip", 1, || {
+        let start = Instant::now();
+        let state = serde_json::to_string(&punter).unwrap();
+        let _: Punter = serde_json::from_str(&state).unwrap();
+        start.elapsed()
+    });
+
+    let diagnostics = Diagnostics { top_k: 0, tree: None };
+    let searches = (0..RUNS).filter_map(|_| {
+        let budget = Duration::from_millis(SEARCH_MS);
+        let (_, stats) = punter.make_move_diagnosed(Instant::now(), budget, &diagnostics);
+        // No stats if the endgame solver took over
+        stats.map(|stats| (stats.iterations as f64 * 1e3 / stats.elapsed_ms, stats.peak_nodes))
+    }).collect::<Vec<_>>();
+    if let Some(&(rate, peak_nodes)) = searches.iter().max_by(|a, b| a.0.partial_cmp(&b.0).unwrap()) {
+        report.line(format!("{:<8} {:<16} {:>10.0} iterations/s {:>10} peak nodes",
+                            name, "mcts", rate, peak_nodes));
+    }
+}
+
+// MCTS with search settings of its own, thinking for SELFPLAY_MS a move
+struct Tuned {
+    config: SearchConfig,
+    punter: Option<Punter>,
+}
+
+fn tuned(config: &SearchConfig) -> Box<dyn Player> {
+    Box::new(Tuned { config: config.clone(), punter: None })
+}
+
+impl Player for Tuned {
+    fn setup(&mut self, input: &Input) -> Result<(), String> {
+        let mut punter = Punter::new(input.clone(), PunterType::MCTS);
+        punter.set_config(self.config.clone());
+        self.punter = Some(punter);
+        Ok(())
+    }
+
+    fn play(&mut self, moves: &[Move], _budget: Duration) -> Result<Move, String> {
+        let begin_time = Instant::now();
+        let punter = self.punter.as_mut().unwrap();
+        punter.process_turn(moves);
+        Ok(punter.make_move(begin_time, Duration::from_millis(SELFPLAY_MS)))
+    }
+
+    fn stop(&mut self, _moves: &[Move], _scores: &[Score]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// The search variants against the default one, heads up. Neither side gets
+// the endgame solver, which would decide all of the sample map.
+fn selfplay(report: &mut Report, name: &str, map: &InputMap, games: usize) {
+    let baseline = SearchConfig { endgame_threshold: 0, ..SearchConfig::default() };
+    let variants = [
+        ("transpositions", SearchConfig { transpositions: true, ..baseline.clone() }),
+        ("no RAVE", SearchConfig { rave_equivalence: 0., ..baseline.clone() }),
+        ("RAVE 100", SearchConfig { rave_equivalence: 100., ..baseline.clone() }),
+        ("RAVE 1000", SearchConfig { rave_equivalence: 1000., ..baseline.clone() }),
+    ];
+    for &(variant, ref config) in &variants {
+        let (mut wins, mut losses, mut totals) = (0, 0, [0, 0]);
+        for game in 0..games {
+            // The variant sits first in even games
+            let first = game % 2;
+            let mut players = vec![tuned(config), tuned(&baseline)];
+            players.rotate_left(first);
+            let result = arena::play_game(map, &Default::default(), &mut players, 0);
+            let (ours, theirs) = (result.scores[first], result.scores[1 - first]);
+            totals[0] += ours;
+            totals[1] += theirs;
+            if ours > theirs {
+                wins += 1;
+            } else if ours < theirs {
+                losses += 1;
+            }
+        }
+        report.line(format!("{:<8} {:<20} vs default: {} games, {} wins to {}, total {} vs {}",
+                            name, variant, games, wins, losses, totals[0], totals[1]));
+    }
+}
+
+fn main() {
+    let sections = env::args().skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<_>>();
+    let wants = |section: &str| sections.is_empty() || sections.iter().any(|s| s == section);
+    let mut report = Report { lines: vec![] };
+    if wants("speed") {
+        println!("{} punters, best of {} runs", PUNTERS, RUNS);
+        for &(name, map) in MAPS {
+            bench(&mut report, name, serde_json::from_str(map).unwrap());
+        }
+        bench(&mut report, "grid", grid_map());
+    }
+    if wants("selfplay") {
+        println!("Self-play, {}ms a move", SELFPLAY_MS);
+        for (&(name, map), &games) in MAPS.iter().zip(SELFPLAY_GAMES) {
+            let map: InputMap = serde_json::from_str(map).unwrap();
+            selfplay(&mut report, name, &map, games);
+        }
+    }
+    report.save();
+}
@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use planner;
+use punter::{Punter, PunterId, RiverIdx, SiteId, SiteIdx};
+
+// Cuts with more rivers than this take too many turns for anyone to block
+const MAX_CUT: usize = 2;
+
+/// Where a punter's plans are fragile. Looks at the punter's usable graph,
+/// the rivers that are free or already held by it, and finds the free
+/// rivers that an opponent could claim to cut the punter off from what it
+/// wants to reach. Run it for ourselves to know what to defend, or for an
+/// opponent to know what to attack.
+#[derive(Debug, Default)]
+pub struct Criticality {
+    pub punter: PunterId,
+
+    // Free rivers whose loss splits the punter's usable graph in two
+    pub bridges: Vec<RiverIdx>,
+
+    // Smallest cuts between the punter's network and each target it could
+    // still be cut off from
+    pub cuts: Vec<Cut>,
+
+    // Indexed by river: the value of all targets the river guards, shared
+    // among the rivers of each cut
+    pub scores: Vec<f64>,
+}
+
+#[derive(Debug)]
+pub struct Cut {
+    pub target: SiteId,
+    // What connecting the target is worth to the punter
+    pub value: u64,
+    pub rivers: Vec<RiverIdx>,
+}
+
+impl Criticality {
+    pub fn new(punter: &Punter, who: PunterId) -> Criticality {
+        let mut analysis = Criticality {
+            punter: who,
+            bridges: bridges(punter, who),
+            cuts: vec![],
+            scores: vec![0.; punter.num_rivers()],
+        };
+        let in_tree = match planner::initial_tree(punter, who) {
+            Some(in_tree) => in_tree,
+            None => return analysis,
+        };
+        let mut targets = planner::unconnected_mines(punter, &in_tree);
+        targets.extend(planner::high_value_sites(punter, &in_tree));
+
+        for target in targets {
+            let rivers = match min_cut(punter, who, &in_tree, target) {
+                Some(rivers) => rivers,
+                None => continue,
+            };
+            let value = target_value(punter, &in_tree, target);
+            for ridx in &rivers {
+                analysis.scores[*ridx] += value as f64 / rivers.len() as f64;
+            }
+            analysis.cuts.push(Cut {
+                target: punter.game.map.sites[target].id,
+                value,
+                rivers,
+            });
+        }
+        analysis
+    }
+
+    /// The river guarding the most value, if any river guards anything
+    pub fn most_critical(&self) -> Option<RiverIdx> {
+        self.scores.iter().enumerate()
+            .filter(|&(_, score)| *score > 0.)
+            .fold(None, |best: Option<(RiverIdx, f64)>, (ridx, score)| match best {
+                Some((_, best_score)) if best_score >= *score => best,
+                _ => Some((ridx, *score)),
+            })
+            .map(|(ridx, _)| ridx)
+    }
+}
+
+fn usable(punter: &Punter, who: PunterId, ridx: RiverIdx) -> bool {
+    let river = punter.river(ridx);
+    river.owner.is_none() || river.is_held_by(who)
+}
+
+// Tarjan's bridge finding over the usable graph. Walks rivers rather than
+// sites so parallel rivers between two sites never count as bridges. The
+// search is iterative since real maps are deep enough to overflow the stack.
+fn bridges(punter: &Punter, who: PunterId) -> Vec<RiverIdx> {
+    let sites = punter.num_sites();
+    let mut order = vec![usize::MAX; sites];
+    let mut low = vec![0; sites];
+    let mut next_order = 0;
+    let mut bridges = vec![];
+
+    for root in 0..sites {
+        if order[root] != usize::MAX {
+            continue;
+        }
+        order[root] = next_order;
+        low[root] = next_order;
+        next_order += 1;
+        // (site, river we came in by, next river to look at)
+        let mut stack: Vec<(SiteIdx, Option<RiverIdx>, usize)> = vec![(root, None, 0)];
+        while let Some(&mut (site, via, ref mut next)) = stack.last_mut() {
+            if let Some(ridx) = punter.game.edges[site].get(*next).cloned() {
+                *next += 1;
+                if Some(ridx) == via || !usable(punter, who, ridx) {
+                    continue;
+                }
+                let neighbor = punter.river(ridx).other_index(site);
+                if order[neighbor] == usize::MAX {
+                    order[neighbor] = next_order;
+                    low[neighbor] = next_order;
+                    next_order += 1;
+                    stack.push((neighbor, Some(ridx), 0));
+                } else {
+                    low[site] = low[site].min(order[neighbor]);
+                }
+                continue;
+            }
+            stack.pop();
+            if let (Some(ridx), Some(&(parent, _, _))) = (via, stack.last()) {
+                low[parent] = low[parent].min(low[site]);
+                if low[site] > order[parent] && punter.river(ridx).owner.is_none() {
+                    bridges.push(ridx);
+                }
+            }
+        }
+    }
+    bridges.sort();
+    bridges
+}
+
+// Unit-capacity max flow from the tree to `target`, where rivers the punter
+// already holds can't be cut. Returns the free rivers of a minimum cut if it
+// has at most MAX_CUT of them, and None if the target is out of reach or too
+// well connected to worry about.
+fn min_cut(punter: &Punter, who: PunterId, in_tree: &[bool],
+           target: SiteIdx) -> Option<Vec<RiverIdx>> {
+    // Net flow through each free river, positive from source to target
+    let mut flow = vec![0i8; punter.num_rivers()];
+    let mut paths = 0;
+    while paths <= MAX_CUT {
+        let via = match residual_search(punter, who, in_tree, &flow, Some(target)) {
+            Some(via) => via,
+            None => break,
+        };
+        let mut site = target;
+        while let Some(ridx) = via[site] {
+            let river = punter.river(ridx);
+            if river.owner.is_none() {
+                flow[ridx] += if site == river.target_idx { 1 } else { -1 };
+            }
+            site = river.other_index(site);
+        }
+        paths += 1;
+    }
+    if paths == 0 || paths > MAX_CUT {
+        return None;
+    }
+
+    // The cut lies between what the tree still reaches and everything else
+    let via = residual_search(punter, who, in_tree, &flow, None).unwrap();
+    let reached = |site: SiteIdx| in_tree[site] || via[site].is_some();
+    let cut = (0..punter.num_rivers())
+        .filter(|ridx| punter.river(*ridx).owner.is_none())
+        .filter(|ridx| {
+            let river = punter.river(*ridx);
+            reached(river.source_idx) != reached(river.target_idx)
+        })
+        .collect();
+    Some(cut)
+}
+
+// BFS from the tree over rivers with spare capacity, recording the river
+// each site was reached by. With a target, gives up unless the target is
+// reached; without one, explores everything reachable.
+fn residual_search(punter: &Punter, who: PunterId, in_tree: &[bool], flow: &[i8],
+                   target: Option<SiteIdx>) -> Option<Vec<Option<RiverIdx>>> {
+    let mut via: Vec<Option<RiverIdx>> = vec![None; in_tree.len()];
+    let mut que = (0..in_tree.len()).filter(|site| in_tree[*site]).collect::<VecDeque<_>>();
+    while let Some(site) = que.pop_front() {
+        if Some(site) == target {
+            return Some(via);
+        }
+        for ridx in &punter.game.edges[site] {
+            let river = punter.river(*ridx);
+            let neighbor = river.other_index(site);
+            if in_tree[neighbor] || via[neighbor].is_some() || !usable(punter, who, *ridx) {
+                continue;
+            }
+            let forward = site == river.source_idx;
+            let spare = river.is_held_by(who)
+                || (forward && flow[*ridx] < 1)
+                || (!forward && flow[*ridx] > -1);
+            if spare {
+                via[neighbor] = Some(*ridx);
+                que.push_back(neighbor);
+            }
+        }
+    }
+    match target {
+        Some(_) => None,
+        None => Some(via),
+    }
+}
+
+// What the punter scores by linking `target` to the tree: every mine in the
+// tree reaches the target, and a target mine reaches the whole tree
+fn target_value(punter: &Punter, in_tree: &[bool], target: SiteIdx) -> u64 {
+    let squared = |dist: usize| if dist == usize::MAX { 0 } else { (dist * dist) as u64 };
+    punter.game.distances.iter().map(|dists| {
+        if dists[target] == 0 {
+            (0..in_tree.len())
+                .filter(|site| in_tree[*site])
+                .map(|site| squared(dists[site]))
+                .sum()
+        } else if (0..in_tree.len()).any(|site| in_tree[site] && dists[site] == 0) {
+            squared(dists[target])
+        } else {
+            0
+        }
+    }).sum()
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use game::GameState;
+use player::Player;
+use protocol::{self, GameRecord, Move};
+use punter::{Input, InputMap, PunterId, Settings, TimeBudget};
+
+/// How a local game went
+#[derive(Debug)]
+pub struct GameResult {
+    // Indexed by seat
+    pub scores: Vec<i64>,
+    // Moves that were replaced by a pass, because they were late, illegal
+    // or never came
+    pub forfeits: Vec<usize>,
+    pub record: GameRecord,
+}
+
+/// Referee a game between `players` on `map`, the way the official server
+/// does: everyone moves in turn, seeing the last move of every punter,
+/// until each river has had its turn. Moves that take longer than `timeout`
+/// seconds (0 for no limit) or that the server would reject count as
+/// passes.
+pub fn play_game(map: &InputMap, settings: &Settings, players: &mut [Box<dyn Player>],
+                 timeout: u8) -> GameResult {
+    let budget = TimeBudget::new(timeout).duration();
+    let limit = Duration::from_secs(timeout as u64);
+    let punters = players.len();
+    let setup = |punter: PunterId| Input {
+        punter,
+        punters,
+        map: map.clone(),
+        settings: settings.clone(),
+    };
+    let mut referee = GameState::new(punters, map.clone(), settings.clone());
+    let mut forfeits = vec![0; punters];
+    let mut zombies = vec![false; punters];
+    for (punter, player) in players.iter_mut().enumerate() {
+        if let Err(e) = player.setup(&setup(punter)) {
+            warn!("arena", "Punter {} failed to set up and will only pass: {}", punter, e);
+            zombies[punter] = true;
+        }
+    }
+
+    let pass = |punter: PunterId| Move::pass (protocol::Pass { punter });
+    let mut last = (0..punters).map(pass).collect::<Vec<_>>();
+    // What punter 0 gets to see, for the record
+    let mut turns = vec![];
+    for turn in 0..referee.num_rivers() {
+        let punter = turn % punters;
+        if punter == 0 {
+            turns.push(last.clone());
+        }
+        let begin_time = Instant::now();
+        let next_move = if zombies[punter] {
+            Err("gave up".to_string())
+        } else {
+            players[punter].play(&last, budget)
+        };
+        let next_move = match next_move {
+            _ if timeout > 0 && begin_time.elapsed() > limit =>
+                Err(format!("took {:?}", begin_time.elapsed())),
+            Ok(ref m) if m.punter() != punter => Err(format!("moved for someone else: {:?}", m)),
+            Ok(m) => referee.apply(&m)
+                .map(|_| m.clone())
+                .map_err(|e| format!("made an illegal move {:?}: {}", m, e)),
+            Err(e) => Err(e),
+        };
+        let next_move = next_move.unwrap_or_else(|e| {
+            if !zombies[punter] {
+                warn!("arena", "Punter {} passes on turn {}: {}", punter, turn, e);
+            }
+            forfeits[punter] += 1;
+            let pass = pass(punter);
+            referee.apply(&pass).unwrap();
+            pass
+        });
+        debug!("arena", "Turn {}: {:?}", turn, next_move);
+        last[punter] = next_move;
+    }
+    turns.push(last.clone());
+
+    let scores = referee.scores();
+    let reported = scores.iter().enumerate()
+        .map(|(punter, score)| protocol::Score { punter, score: *score as isize })
+        .collect::<Vec<_>>();
+    for (punter, player) in players.iter_mut().enumerate() {
+        if let Err(e) = player.stop(&last, &reported) {
+            warn!("arena", "Punter {} failed to stop: {}", punter, e);
+        }
+    }
+    GameResult {
+        scores,
+        forfeits,
+        record: GameRecord { setup: setup(0), turns },
+    }
+}
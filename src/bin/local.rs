@@ -0,0 +1,110 @@
+extern crate getopts;
+extern crate serde_json;
+
+use getopts::Options;
+use std::env;
+use std::fs::File;
+
+#[macro_use]
+extern crate punter as p;
+use p::arena;
+use p::logging;
+use p::player::{Builtin, External, Player};
+use p::punter::{InputMap, PunterType, Settings};
+
+const DEFAULT_TIMEOUT: &str = "1";
+const DEFAULT_GAMES: &str = "1";
+const DEFAULT_LOG: &str = "warn";
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] MAP PLAYER...\n\n\
+                         Play local games on MAP (a map, or a setup message \
+                         with settings). Each PLAYER is one of our AIs (random, \
+                         mcts, greedy, connect-mines, block-leader or steiner) \
+                         or the command line of a bot speaking the offline \
+                         protocol, e.g. \"python3 bot.py\". With several games, \
+                         seats rotate from game to game.", program);
+    eprintln!("{}", opts.usage(&brief));
+}
+
+fn player(spec: &str) -> Box<dyn Player> {
+    match spec.parse::<PunterType>() {
+        Ok(ai) => Box::new(Builtin::new(ai)),
+        Err(_) => Box::new(External::from_command(spec).expect("Empty player command")),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optopt("g", "games", "games to play", "GAMES");
+    opts.optopt("t", "timeout", "move timeout in seconds, 0 for none", "TIMEOUT");
+    opts.optopt("", "record", "record every game into DIR", "DIR");
+    opts.optopt("", "log", "what to log, e.g. info,arena=debug (default: $PUNTER_LOG or warn)", "SPEC");
+    opts.optopt("", "log-file", "log to FILE instead of stderr", "FILE");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).unwrap();
+    if matches.opt_present("h") || matches.free.len() < 2 {
+        print_usage(&program, opts);
+        return;
+    }
+    logging::init(matches.opt_str("log").as_deref(), DEFAULT_LOG,
+                  matches.opt_str("log-file").as_deref())
+        .unwrap();
+
+    let games: usize = matches.opt_str("games").unwrap_or(DEFAULT_GAMES.to_string())
+        .parse().unwrap();
+    let timeout: u8 = matches.opt_str("timeout").unwrap_or(DEFAULT_TIMEOUT.to_string())
+        .parse().unwrap();
+    let record_dir = matches.opt_str("record");
+
+    let file = File::open(&matches.free[0]).expect("Could not open map");
+    let mut map: serde_json::Value = serde_json::from_reader(file).expect("Could not parse map");
+    let settings: Settings = match map.get("settings") {
+        Some(settings) => serde_json::from_value(settings.clone()).expect("Could not parse settings"),
+        None => Settings::default(),
+    };
+    if let Some(inner) = map.get_mut("map").map(|inner| inner.take()) {
+        map = inner;
+    }
+    let map: InputMap = serde_json::from_value(map).expect("Could not parse map");
+
+    let specs = &matches.free[1..];
+    let mut totals = vec![0; specs.len()];
+    let mut wins = vec![0; specs.len()];
+    for game in 0..games {
+        // Seat s goes to player (s + game) % players
+        let seated = (0..specs.len()).map(|seat| (seat + game) % specs.len()).collect::<Vec<_>>();
+        let mut players = seated.iter().map(|p| player(&specs[*p])).collect::<Vec<_>>();
+        let result = arena::play_game(&map, &settings, &mut players, timeout);
+
+        let best = result.scores.iter().cloned().max().unwrap_or(0);
+        let line = seated.iter().zip(&result.scores).zip(&result.forfeits)
+            .map(|((p, score), forfeits)| {
+                totals[*p] += score;
+                if *score == best {
+                    wins[*p] += 1;
+                }
+                match *forfeits {
+                    0 => format!("{} {}", specs[*p], score),
+                    n => format!("{} {} ({} forfeited)", specs[*p], score, n),
+                }
+            })
+            .collect::<Vec<_>>();
+        println!("Game {}: {}", game, line.join(", "));
+
+        if let Some(ref dir) = record_dir {
+            let path = format!("{}/game-{}.json", dir, game);
+            let file = File::create(&path).expect("Could not create game record");
+            serde_json::to_writer(file, &result.record).expect("Could not write game record");
+            info!("arena", "Recorded game to {}", path);
+        }
+    }
+    if games > 1 {
+        for (p, spec) in specs.iter().enumerate() {
+            println!("{}: {} wins, {} points", spec, wins[p], totals[p]);
+        }
+    }
+}
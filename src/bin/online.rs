@@ -1,3 +1,6 @@
+#[macro_use]
+extern crate serde_derive;
+
 extern crate getopts;
 extern crate bufstream;
 extern crate serde;
@@ -6,29 +9,88 @@ extern crate rand;
 
 use getopts::Options;
 use std::env;
+use std::fs::File;
 use std::net::TcpStream;
+use std::thread;
 use bufstream::BufStream;
-use std::io::{Read, Write, BufRead};
-use std::time::Instant;
+use std::io::{self, Read, Write, BufRead};
+use std::time::{Duration, Instant};
 
+#[macro_use]
 extern crate punter as p;
+use p::logging::{self, Level, TurnRecord};
 use p::protocol;
-use p::punter::Punter;
+use p::punter::{Diagnostics, Punter, PunterType, SearchStats, TimeBudget, TreeFormat};
 use p::punter;
 
 const DEFAULT_SERVER: &str = "punter.inf.ed.ac.uk";
 const DEFAULT_PORT: &str = "9001";
 const DEFAULT_NAME: &str = "random hackers";
 const DEFAULT_TIMEOUT: &str = "1";
+const DEFAULT_GAMES: &str = "1";
+const DEFAULT_AI: &str = "mcts";
+const DEFAULT_TREE_FORMAT: &str = "dot";
+const DEFAULT_LOG: &str = "info";
+// Root children listed per move with --diagnostics
+const DIAGNOSTICS_TOP_K: usize = 5;
+
+/// How to play every game of the session
+#[derive(Clone, Debug)]
+struct GameOptions {
+    timeout: u8,
+    ai: PunterType,
+    diagnostics: Option<Diagnostics>,
+    // Directory to write each move's search tree to
+    dump_dir: Option<String>,
+    // Directory to record every game to, for replaying and rendering
+    record_dir: Option<String>,
+}
+
+/// Outcome of a single online game, one row of the session summary
+#[derive(Serialize, Debug)]
+struct GameSummary {
+    server: String,
+    port: u16,
+    // The map's name if the server gave one, otherwise its hash
+    map: String,
+    sites: usize,
+    rivers: usize,
+    punters: usize,
+    punter: usize,
+    rank: usize,
+    score: isize,
+    moves: usize,
+    avg_move_ms: f64,
+}
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options]", program);
     println!("{}", opts.usage(&brief));
 }
 
-fn send_message<T: ?Sized>(stream: &mut BufStream<TcpStream>, msg: &T)
+// Standard input and output as one stream, to play a game over pipes
+struct StdioStream;
+
+impl Read for StdioStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buf)
+    }
+}
+
+impl Write for StdioStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+fn send_message<S, T>(stream: &mut BufStream<S>, msg: &T)
     where
-    T: serde::Serialize,
+    S: Read + Write,
+    T: serde::Serialize + ?Sized,
 {
     let msg_str = serde_json::to_string(msg).expect("Could not encode message as JSON");
 
@@ -36,63 +98,171 @@ fn send_message<T: ?Sized>(stream: &mut BufStream<TcpStream>, msg: &T)
     stream.flush().unwrap();
 }
 
-fn recv_message<T>(stream: &mut BufStream<TcpStream>) -> Result<T, serde_json::Error>
-    where T: serde::de::DeserializeOwned
+fn recv_message<S, T>(stream: &mut BufStream<S>) -> Result<T, serde_json::Error>
+    where S: Read + Write,
+          T: serde::de::DeserializeOwned
 {
     let mut buf = vec![];
-    let _ = stream.read_until(':' as u8, &mut buf);
+    let _ = stream.read_until(b':', &mut buf);
     buf.pop(); // Drop colon
     let len = String::from_utf8(buf).unwrap()
         .parse::<u64>().unwrap();
     serde_json::from_reader(stream.take(len))
 }
 
-fn online_handshake(stream: &mut BufStream<TcpStream>, name: String) {
+fn online_handshake<S: Read + Write>(stream: &mut BufStream<S>, name: String) {
     send_message(stream, &punter::handshake(name));
     let handshake: protocol::HandshakeS = recv_message(stream)
         .expect("Could not parse handshake response");
-    println!("Registered as: {}", handshake.you);
+    info!("game", "Registered as: {}", handshake.you);
+}
+
+// Print the search report for a move and write out its tree, if we have one
+fn report_search(stats: &SearchStats, options: &GameOptions, game: usize, turn: usize) {
+    logging::record(Level::Info, "search", stats);
+    if let (Some(tree), Some(dir), Some(diagnostics)) = (&stats.tree, &options.dump_dir, &options.diagnostics) {
+        let format = diagnostics.tree.unwrap();
+        let path = format!("{}/game-{}-turn-{}.{}", dir, game, turn, format.extension());
+        let mut file = File::create(&path).expect("Could not create tree dump");
+        file.write_all(tree.as_bytes()).expect("Could not write tree dump");
+    }
 }
 
-fn online_game_loop(stream: &mut BufStream<TcpStream>, timeout: u8) {
-    // let setup_begin = Instant::now();
+fn online_game_loop<S: Read + Write>(stream: &mut BufStream<S>, options: &GameOptions,
+                                    server: &str, port: u16, game: usize) -> GameSummary {
     let setup_input: punter::Input = recv_message(stream)
         .expect("Could not parse setup message");
 
-    let mut punter = Punter::new(setup_input, punter::PunterType::MCTS);
-    println!("We are player {}", punter.id());
+    let mut record = options.record_dir.as_ref().map(|_| protocol::GameRecord {
+        setup: setup_input.clone(),
+        turns: vec![],
+    });
+    let mut punter = Punter::new(setup_input, options.ai);
+    info!("game", "We are player {}", punter.id());
 
     let ready_msg = protocol::ReadyP {
         ready: punter.id(),
     };
     send_message(stream, &ready_msg);
-    // let setup_time = setup_begin.elapsed();
-    // println!("Setup took {}.{:09}s", setup_time.as_secs(), setup_time.subsec_nanos());
 
+    let mut budget = TimeBudget::new(options.timeout);
+    let mut move_count = 0;
+    let mut move_time = Duration::from_secs(0);
     loop {
         let turn: protocol::TurnS = recv_message(stream)
             .expect("Could not parse turn");
         let turn_begin = Instant::now();
-        // println!("{:#?}", turn);
+        trace!("protocol", "{:?}", turn);
         match turn {
             protocol::TurnS::timeout (_) => {
-                println!("Timout!");
+                budget.timed_out();
+                warn!("game", "Timeout! Cutting move budget to {:?}", budget.duration());
             }
             protocol::TurnS::turn {moves} => {
                 punter.process_turn(&moves);
-                let next_move = punter.make_move(turn_begin, timeout);
-                // println!("{:?}", next_move);
+                if let Some(ref mut record) = record {
+                    record.turns.push(moves);
+                }
+                // Without --diagnostics, search just for the iteration count
+                let diagnostics = options.diagnostics
+                    .unwrap_or(Diagnostics { top_k: 0, tree: None });
+                let (next_move, stats) = punter.make_move_diagnosed(
+                    turn_begin, budget.duration(), &diagnostics);
                 send_message(stream, &next_move);
+                logging::record(Level::Info, "turn", &TurnRecord {
+                    turn: punter.turn(),
+                    next_move: &next_move,
+                    budget_ms: budget.duration().as_secs_f64() * 1000.,
+                    elapsed_ms: turn_begin.elapsed().as_secs_f64() * 1000.,
+                    iterations: stats.as_ref().map(|stats| stats.iterations),
+                    peak_nodes: stats.as_ref().map(|stats| stats.peak_nodes),
+                });
+                if let (Some(stats), true) = (stats, options.diagnostics.is_some()) {
+                    report_search(&stats, options, game, punter.turn());
+                }
+                move_count += 1;
+                move_time += turn_begin.elapsed();
             }
-            protocol::TurnS::stop{scores, moves: _} => {
-                println!("Done with game. Scores: {:?}", scores);
-                println!("Our score: {:?}", scores[punter.id()]);
-                break;
+            protocol::TurnS::stop{scores, moves} => {
+                punter.process_stop(&moves);
+                if let (Some(mut record), Some(dir)) = (record.take(), &options.record_dir) {
+                    record.turns.push(moves);
+                    let path = format!("{}/game-{}.json", dir, game);
+                    let file = File::create(&path).expect("Could not create game record");
+                    serde_json::to_writer(file, &record).expect("Could not write game record");
+                    info!("game", "Recorded game to {}", path);
+                }
+                info!("game", "Done with game. Scores: {:?}", scores);
+                info!("game", "Our score: {}", punter.scores()[punter.id()]);
+                for mismatch in punter.score_mismatches(&scores) {
+                    warn!("game", "Score mismatch for punter {}: computed {:?}, server reported {}",
+                             mismatch.punter, mismatch.computed, mismatch.reported);
+                }
+                let score = scores.iter()
+                    .find(|s| s.punter == punter.id())
+                    .map_or(0, |s| s.score);
+                return GameSummary {
+                    server: server.to_string(),
+                    port,
+                    map: punter.map_field("name")
+                        .and_then(|name| name.as_str())
+                        .map_or_else(|| format!("{:016x}", punter.map_hash()), |name| name.to_string()),
+                    sites: punter.num_sites(),
+                    rivers: punter.num_rivers(),
+                    punters: punter.punters(),
+                    punter: punter.id(),
+                    rank: 1 + scores.iter().filter(|s| s.score > score).count(),
+                    score,
+                    moves: move_count,
+                    avg_move_ms: if move_count == 0 {
+                        0.
+                    } else {
+                        move_time.as_secs_f64() * 1000. / move_count as f64
+                    },
+                };
             }
         };
     }
 }
 
+fn play_game(server: &str, port: u16, name: &str, options: &GameOptions,
+             game: usize) -> GameSummary {
+    let connection = TcpStream::connect((server, port))
+        .expect("Connection refused!");
+    connection.set_nodelay(true).unwrap();
+    let mut stream = BufStream::new(connection);
+
+    online_handshake(&mut stream, name.to_string());
+    online_game_loop(&mut stream, options, server, port, game)
+}
+
+// A single game with the server on the other end of stdin and stdout. Only
+// meant for tests, which can script a server without opening a socket.
+fn play_stdio_game(name: &str, options: &GameOptions) -> GameSummary {
+    let mut stream = BufStream::new(StdioStream);
+    online_handshake(&mut stream, name.to_string());
+    online_game_loop(&mut stream, options, "stdio", 0, 0)
+}
+
+/// Write the session summary as JSON if the file name ends in .json, and as
+/// CSV otherwise.
+fn write_summary(path: &str, games: &[GameSummary]) {
+    let mut file = File::create(path).expect("Could not create summary file");
+    if path.ends_with(".json") {
+        serde_json::to_writer_pretty(&mut file, games)
+            .expect("Could not write summary");
+        return;
+    }
+    writeln!(file, "server,port,map,sites,rivers,punters,punter,rank,score,moves,avg_move_ms")
+        .expect("Could not write summary");
+    for g in games {
+        writeln!(file, "{},{},{},{},{},{},{},{},{},{},{:.3}",
+                 g.server, g.port, g.map, g.sites, g.rivers, g.punters, g.punter,
+                 g.rank, g.score, g.moves, g.avg_move_ms)
+            .expect("Could not write summary");
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
@@ -100,27 +270,83 @@ fn main() {
     let mut opts = Options::new();
     opts.optopt("s", "server", "server address", "ADDRESS");
     opts.optopt("p", "port", "port", "PORT");
+    opts.optopt("P", "ports", "comma-separated ports to play a session on", "PORTS");
+    opts.optopt("g", "games", "games to play on each port", "GAMES");
+    opts.optflag("", "parallel", "play all games of the session at once");
+    opts.optflag("", "stdio", "testing hook: play a single game over stdin and stdout \
+                               instead of a server");
+    opts.optopt("", "summary", "write a per-game summary (CSV, or JSON for *.json)", "FILE");
     opts.optopt("n", "name", "AI name", "NAME");
     opts.optopt("t", "timeout", "Move timeout", "TIMEOUT");
+    opts.optopt("a", "ai", "AI to play with: random, mcts, greedy, connect-mines, \
+                           block-leader or steiner", "AI");
+    opts.optopt("", "record", "record every game into DIR", "DIR");
+    opts.optflag("", "diagnostics", "print a report on the search behind every move");
+    opts.optopt("", "dump-tree", "write every move's search tree into DIR", "DIR");
+    opts.optopt("", "tree-format", "format of tree dumps: dot or json", "FORMAT");
+    opts.optopt("", "log", "what to log, e.g. warn,turn=info (default: $PUNTER_LOG or info)", "SPEC");
+    opts.optopt("", "log-file", "log to FILE instead of stderr", "FILE");
     opts.optflag("h", "help", "print this help menu");
     let matches = opts.parse(&args[1..]).unwrap();
     if matches.opt_present("h") {
         print_usage(&program, opts);
         return;
     }
+    logging::init(matches.opt_str("log").as_deref(), DEFAULT_LOG,
+                  matches.opt_str("log-file").as_deref())
+        .unwrap();
 
     let server = matches.opt_str("server").unwrap_or(DEFAULT_SERVER.to_string());
-    let port: u16 = matches.opt_str("port").unwrap_or(DEFAULT_PORT.to_string())
-        .parse().unwrap();
+    let ports: Vec<u16> = matches.opt_str("ports")
+        .or_else(|| matches.opt_str("port"))
+        .unwrap_or(DEFAULT_PORT.to_string())
+        .split(',')
+        .map(|port| port.trim().parse().unwrap())
+        .collect();
     let name = matches.opt_str("name").unwrap_or(DEFAULT_NAME.to_string());
     let timeout: u8 = matches.opt_str("timeout").unwrap_or(DEFAULT_TIMEOUT.to_string())
         .parse().unwrap();
+    let games: usize = matches.opt_str("games").unwrap_or(DEFAULT_GAMES.to_string())
+        .parse().unwrap();
+    let ai: PunterType = matches.opt_str("ai").unwrap_or(DEFAULT_AI.to_string())
+        .parse().unwrap();
+    let dump_dir = matches.opt_str("dump-tree");
+    let tree_format: TreeFormat = matches.opt_str("tree-format")
+        .unwrap_or(DEFAULT_TREE_FORMAT.to_string())
+        .parse().unwrap();
+    let diagnostics = if matches.opt_present("diagnostics") || dump_dir.is_some() {
+        Some(Diagnostics {
+            top_k: DIAGNOSTICS_TOP_K,
+            tree: dump_dir.as_ref().map(|_| tree_format),
+        })
+    } else {
+        None
+    };
+    let record_dir = matches.opt_str("record");
+    let options = GameOptions { timeout, ai, diagnostics, dump_dir, record_dir };
 
-    let connection = TcpStream::connect((&server[..], port))
-        .expect("Connection refused!");
-    connection.set_nodelay(true).unwrap();
-    let mut stream = BufStream::new(connection);
+    let session = ports.iter()
+        .flat_map(|port| (0..games).map(move |_| *port))
+        .collect::<Vec<_>>();
+    let summaries = if matches.opt_present("stdio") {
+        vec![play_stdio_game(&name, &options)]
+    } else if matches.opt_present("parallel") {
+        let handles = session.into_iter().enumerate().map(|(game, port)| {
+            let server = server.clone();
+            let name = name.clone();
+            let options = options.clone();
+            thread::spawn(move || play_game(&server, port, &name, &options, game))
+        }).collect::<Vec<_>>();
+        handles.into_iter()
+            .map(|handle| handle.join().expect("Game thread panicked"))
+            .collect::<Vec<_>>()
+    } else {
+        session.into_iter().enumerate()
+            .map(|(game, port)| play_game(&server, port, &name, &options, game))
+            .collect::<Vec<_>>()
+    };
 
-    online_handshake(&mut stream, name);
-    online_game_loop(&mut stream, timeout);
+    if let Some(path) = matches.opt_str("summary") {
+        write_summary(&path, &summaries);
+    }
 }
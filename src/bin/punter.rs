@@ -4,18 +4,35 @@ extern crate serde;
 extern crate serde_json;
 extern crate rand;
 
+use getopts::Options;
+use std::env;
+use std::fs::File;
 use std::io::{self, Read, Write, BufRead};
 use std::time::Instant;
 
+#[macro_use]
 extern crate punter as p;
+use p::logging::{self, Level, TurnRecord};
 use p::protocol;
-use p::punter::Punter;
+use p::punter::{Diagnostics, Punter, PunterType, TimeBudget, TreeFormat};
 use p::punter;
 
 const NAME: &str = "random hackers";
 const TIMEOUT: u8 = 1;
+const DEFAULT_AI: &str = "mcts";
+const DEFAULT_TREE_FORMAT: &str = "dot";
+// Every step is a new process, so keep quiet unless asked
+const DEFAULT_LOG: &str = "warn";
+// Root children listed per move with --diagnostics
+const DIAGNOSTICS_TOP_K: usize = 5;
 
-struct OfflineGame;
+struct OfflineGame {
+    // Only used at setup; later steps play with the AI stored in the state
+    ai: PunterType,
+    diagnostics: Option<Diagnostics>,
+    // Directory to write each move's search tree to
+    dump_dir: Option<String>,
+}
 
 impl OfflineGame {
     fn run(&mut self) {
@@ -23,8 +40,8 @@ impl OfflineGame {
         self.game_step(TIMEOUT);
     }
 
-    fn send_message<T: ?Sized>(&mut self, msg: &T)
-        where T: serde::Serialize,
+    fn send_message<T>(&mut self, msg: &T)
+        where T: serde::Serialize + ?Sized,
     {
         let mut writer = io::stdout();
         let msg_str = serde_json::to_string(msg).expect("Could not encode message as JSON");
@@ -39,7 +56,7 @@ impl OfflineGame {
         where T: serde::de::DeserializeOwned
     {
         let mut buf = vec![];
-        let _ = reader.read_until(':' as u8, &mut buf);
+        let _ = reader.read_until(b':', &mut buf);
         buf.pop(); // Drop colon
         let len = String::from_utf8(buf).unwrap()
             .parse::<u64>().unwrap();
@@ -52,7 +69,7 @@ impl OfflineGame {
         self.send_message(&punter::handshake(name));
         let handshake: protocol::HandshakeS = self.recv_message(reader)
             .expect("Could not parse handshake response");
-        // eprintln!("Registered as: {}", handshake.you);
+        debug!("game", "Registered as: {}", handshake.you);
     }
 
     fn game_step(&mut self, timeout: u8) {
@@ -63,8 +80,8 @@ impl OfflineGame {
 
         match setup_input {
             protocol::OfflineInput::Setup (setup_input) => {
-                let punter = Punter::new(setup_input, punter::PunterType::MCTS);
-                // eprintln!("We are player {}", punter.id());
+                let punter = Punter::new(setup_input, self.ai);
+                info!("game", "We are player {}", punter.id());
 
                 let ready_msg = protocol::OfflineReadyP {
                     ready: punter.id(),
@@ -76,43 +93,91 @@ impl OfflineGame {
                 protocol::OfflineTurn {turn, mut state}
             ) => {
                 state.process_turn(&turn.moves);
-                let next_move = state.make_move(time_begin, timeout);
-                match next_move {
-                    protocol::Move::claim (claim) =>
-                        self.send_message(&protocol::OfflineClaim {
-                            claim: claim,
-                            state: state,
-                        }),
-                    protocol::Move::pass (pass) =>
-                        self.send_message(&protocol::OfflinePass {
-                            pass: pass,
-                            state: state,
-                        }),
-                    protocol::Move::splurge (splurge) =>
-                        self.send_message(&protocol::OfflineSplurge {
-                            splurge: splurge,
-                            state: state,
-                        }),
-                    protocol::Move::option (option) =>
-                        self.send_message(&protocol::OfflineOption {
-                            option: option,
-                            state: state,
-                        }),
-                };
+                // Sending the state back is part of the move too
+                let budget = TimeBudget::new(timeout).duration()
+                    .checked_sub(state.state_reserve())
+                    .unwrap_or_default();
+                // Without --diagnostics, search just for the iteration count
+                let diagnostics = self.diagnostics
+                    .unwrap_or(Diagnostics { top_k: 0, tree: None });
+                let (next_move, stats) = state.make_move_diagnosed(time_begin, budget, &diagnostics);
+                logging::record(Level::Info, "turn", &TurnRecord {
+                    turn: state.turn(),
+                    next_move: &next_move,
+                    budget_ms: budget.as_secs_f64() * 1000.,
+                    elapsed_ms: time_begin.elapsed().as_secs_f64() * 1000.,
+                    iterations: stats.as_ref().map(|stats| stats.iterations),
+                    peak_nodes: stats.as_ref().map(|stats| stats.peak_nodes),
+                });
+                let turn = state.turn();
+                let stats = stats.filter(|_| self.diagnostics.is_some());
+                if let Some(ref stats) = stats {
+                    logging::record(Level::Info, "search", stats);
+                }
+                self.send_message(&protocol::OfflineMove::new(next_move, state));
+                // The tree can wait until the move is out
+                if let (Some(tree), Some(dir)) = (stats.and_then(|stats| stats.tree), &self.dump_dir) {
+                    let path = format!("{}/turn-{}.{}", dir, turn,
+                                       diagnostics.tree.unwrap().extension());
+                    File::create(&path)
+                        .and_then(|mut file| file.write_all(tree.as_bytes()))
+                        .expect("Could not write tree dump");
+                }
             }
             protocol::OfflineInput::Stop (
-                protocol::OfflineStop {stop, state}
+                protocol::OfflineStop {stop, mut state}
             ) => {
-                // eprintln!("Done with game. Scores: {:?}", stop.scores);
-                // eprintln!("Our score: {:?}", stop.scores[state.id()]);
+                state.process_stop(&stop.moves);
+                info!("game", "Done with game. Scores: {:?}", stop.scores);
+                info!("game", "Our score: {}", state.scores()[state.id()]);
             }
-            protocol::OfflineInput::Timeout {timeout: _} => {
-                // eprintln!("Timout!");
+            protocol::OfflineInput::Timeout {timeout} => {
+                warn!("game", "Timeout! Our last move took longer than {}s", timeout);
             }
         }
     }
 }
 
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options]", program);
+    eprintln!("{}", opts.usage(&brief));
+}
+
 fn main() {
-    OfflineGame.run();
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optopt("a", "ai", "AI to play with: random, mcts, greedy, connect-mines, \
+                           block-leader or steiner", "AI");
+    opts.optflag("", "diagnostics", "print a report on the search behind every move to stderr");
+    opts.optopt("", "dump-tree", "write every move's search tree into DIR", "DIR");
+    opts.optopt("", "tree-format", "format of tree dumps: dot or json", "FORMAT");
+    opts.optopt("", "log", "what to log, e.g. info,search=off (default: $PUNTER_LOG or warn)", "SPEC");
+    opts.optopt("", "log-file", "log to FILE instead of stderr", "FILE");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).unwrap();
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+    logging::init(matches.opt_str("log").as_deref(), DEFAULT_LOG,
+                  matches.opt_str("log-file").as_deref())
+        .unwrap();
+
+    let ai: PunterType = matches.opt_str("ai").unwrap_or(DEFAULT_AI.to_string())
+        .parse().unwrap();
+    let dump_dir = matches.opt_str("dump-tree");
+    let tree_format: TreeFormat = matches.opt_str("tree-format")
+        .unwrap_or(DEFAULT_TREE_FORMAT.to_string())
+        .parse().unwrap();
+    let diagnostics = if matches.opt_present("diagnostics") || dump_dir.is_some() {
+        Some(Diagnostics {
+            top_k: DIAGNOSTICS_TOP_K,
+            tree: dump_dir.as_ref().map(|_| tree_format),
+        })
+    } else {
+        None
+    };
+    OfflineGame { ai, diagnostics, dump_dir }.run();
 }
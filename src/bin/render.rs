@@ -0,0 +1,53 @@
+extern crate getopts;
+extern crate serde_json;
+
+use getopts::Options;
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+
+extern crate punter as p;
+use p::protocol::GameRecord;
+use p::punter::Input;
+use p::render;
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] GAME\n\n\
+                         Draw a recorded game (from online --record) or a setup \
+                         message as SVG.", program);
+    eprintln!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optopt("t", "turn", "draw the game after this many turns (default: the end)", "TURN");
+    opts.optopt("o", "output", "write the SVG to FILE instead of stdout", "FILE");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).unwrap();
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let file = File::open(&matches.free[0]).expect("Could not open game");
+    let game: serde_json::Value = serde_json::from_reader(file).expect("Could not parse game");
+    let record: GameRecord = if game.get("setup").is_some() {
+        serde_json::from_value(game).expect("Could not parse game record")
+    } else {
+        let setup: Input = serde_json::from_value(game).expect("Could not parse setup");
+        GameRecord { setup, turns: vec![] }
+    };
+    let turns = matches.opt_str("turn").map_or(record.turns.len(), |t| t.parse().unwrap());
+
+    let game = render::replay(&record, turns).expect("Could not replay game");
+    let svg = render::render_svg(&game);
+    match matches.opt_str("output") {
+        Some(path) => File::create(path)
+            .and_then(|mut file| file.write_all(svg.as_bytes()))
+            .expect("Could not write SVG"),
+        None => io::stdout().write_all(svg.as_bytes()).unwrap(),
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use punter::{Game, InternalGameState, Punter, PunterId, RiverIdx};
+
+// Opponents whose claims extend their network less often than this are
+// played as random rather than adversarial
+const RANDOM_GREEDINESS: f64 = 0.5;
+
+/// Search the rest of the game exactly and return our best claim. We
+/// maximize our search score, opponents that look like random players pick
+/// uniformly (expectimax), and everyone else tries to minimize it (paranoid
+/// minimax). Nobody passes, since a claim never lowers the claimer's score.
+/// Returns None if the search didn't finish within the budget.
+pub(crate) fn solve(punter: &Punter, begin_time: Instant, budget: Duration) -> Option<RiverIdx> {
+    let mut game = InternalGameState::new(punter);
+    game.reset(None);
+    let mut free = game.available_actions().to_vec();
+    free.sort();
+    let mut solver = Solver {
+        punter,
+        game,
+        words: free.len().div_ceil(64),
+        free,
+        cache: HashMap::new(),
+        begin_time,
+        budget,
+    };
+    solver.best_move()
+}
+
+enum Node {
+    Max,
+    Min,
+    Chance,
+}
+
+struct Solver<'a> {
+    punter: &'a Punter,
+    game: InternalGameState<'a>,
+    // Rivers that were free at the root, in index order
+    free: Vec<RiverIdx>,
+    words: usize,
+    // Search value of every position seen, keyed by who owns which of the
+    // free rivers. Whose turn it is follows from the number of claims.
+    cache: HashMap<Vec<u64>, f64>,
+    begin_time: Instant,
+    budget: Duration,
+}
+
+impl<'a> Solver<'a> {
+    fn best_move(&mut self) -> Option<RiverIdx> {
+        let me = self.game.current_player();
+        let mut best = None;
+        let mut best_value = f64::NEG_INFINITY;
+        for ridx in self.moves() {
+            self.game.make_move(ridx);
+            let value = self.value();
+            self.game.undo_move(ridx, me);
+            let value = value?;
+            if value > best_value {
+                best_value = value;
+                best = Some(ridx);
+            }
+        }
+        best
+    }
+
+    fn value(&mut self) -> Option<f64> {
+        if self.begin_time.elapsed() >= self.budget {
+            return None;
+        }
+        let key = self.key();
+        if let Some(value) = self.cache.get(&key) {
+            return Some(*value);
+        }
+
+        let moves = self.moves();
+        let value = if moves.is_empty() {
+            self.game.score()
+        } else {
+            let mover = self.game.current_player();
+            let mut values = Vec::with_capacity(moves.len());
+            for ridx in moves {
+                self.game.make_move(ridx);
+                let value = self.value();
+                self.game.undo_move(ridx, mover);
+                values.push(value?);
+            }
+            match self.node(mover) {
+                Node::Max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                Node::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+                Node::Chance => values.iter().sum::<f64>() / values.len() as f64,
+            }
+        };
+        self.cache.insert(key, value);
+        Some(value)
+    }
+
+    fn moves(&self) -> Vec<RiverIdx> {
+        let mut moves = self.game.available_actions().to_vec();
+        moves.sort();
+        moves
+    }
+
+    fn node(&self, mover: PunterId) -> Node {
+        if mover == self.punter.id() {
+            return Node::Max;
+        }
+        match self.punter.opponent(mover) {
+            Some(model) if model.is_informed() && model.greediness() < RANDOM_GREEDINESS => Node::Chance,
+            _ => Node::Min,
+        }
+    }
+
+    // One bitset over the free rivers per punter
+    fn key(&self) -> Vec<u64> {
+        let mut key = vec![0u64; self.words * self.punter.punters()];
+        for (bit, ridx) in self.free.iter().enumerate() {
+            if let Some(owner) = self.game.river_owner(*ridx) {
+                key[owner * self.words + bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        key
+    }
+}
@@ -0,0 +1,357 @@
+extern crate bincode;
+
+use std::collections::VecDeque;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+
+use graph::{Adjacency, SiteIndex};
+use protocol::{self, Future, Move};
+use punter::{InputMap, PunterId, River, RiverIdx, SiteId, SiteIdx, Settings};
+
+/// The rules of the game and nothing else: who holds which river, which
+/// moves the server accepts and what everyone scores. Meant for anything
+/// that has to follow a game without playing it, like a server or a
+/// visualizer, and for strategies that want to look ahead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameState {
+    pub(crate) punters: PunterId,
+    // Plain JSON rather than bincode, which can't carry the maps' unknown
+    // fields
+    pub(crate) map: InputMap,
+    pub(crate) settings: Settings,
+
+    // Reverse id-to-idx mappings
+    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
+    pub(crate) site_index: SiteIndex,
+    // For every site, all its rivers, sorted by the site at the other end
+    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
+    pub(crate) edges: Adjacency,
+    // Site index of every mine
+    pub(crate) mines: Vec<SiteIdx>,
+    // Indexed by mine, then site; usize::MAX where the mine can't reach
+    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
+    pub(crate) distances: Vec<Vec<usize>>,
+
+    // Moves applied so far, passes included, and who made the last one
+    moves: usize,
+    last_mover: Option<PunterId>,
+
+    // Indexed by punter
+    credits: Vec<usize>,
+    options_used: Vec<usize>,
+    futures: Vec<Vec<Future>>,
+}
+
+impl GameState {
+    pub fn new(punters: PunterId, map: InputMap, settings: Settings) -> GameState {
+        let mut map = map;
+        let site_index = SiteIndex::new(map.sites.iter().map(|site| site.id));
+        for river in &mut map.rivers {
+            river.source_idx = site_index[&river.source];
+            river.target_idx = site_index[&river.target];
+        }
+        let edges = Adjacency::new(map.sites.len(), &map.rivers);
+        let mines = map.mines.iter().map(|mine| site_index[mine]).collect::<Vec<_>>();
+        let distances = mines.iter().map(|mine| edges.distances(&map.rivers, *mine)).collect();
+        GameState {
+            punters,
+            map,
+            settings,
+            site_index,
+            edges,
+            mines,
+            distances,
+            moves: 0,
+            last_mover: None,
+            credits: vec![0; punters],
+            options_used: vec![0; punters],
+            futures: vec![vec![]; punters],
+        }
+    }
+
+    pub fn punters(&self) -> PunterId {
+        self.punters
+    }
+
+    pub fn num_sites(&self) -> usize {
+        self.map.sites.len()
+    }
+
+    pub fn num_rivers(&self) -> usize {
+        self.map.rivers.len()
+    }
+
+    pub fn mines(&self) -> &[SiteId] {
+        &self.map.mines
+    }
+
+    /// Site ids at both ends of a river
+    pub fn river_sites(&self, river: RiverIdx) -> (SiteId, SiteId) {
+        let river = &self.map.rivers[river];
+        (river.source, river.target)
+    }
+
+    pub fn river_owner(&self, river: RiverIdx) -> Option<PunterId> {
+        self.map.rivers[river].owner
+    }
+
+    pub fn river_renter(&self, river: RiverIdx) -> Option<PunterId> {
+        self.map.rivers[river].renter
+    }
+
+    /// Moves applied so far, passes included
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    /// The game lasts one move per river, whether they get claimed or not
+    pub fn total_moves(&self) -> usize {
+        self.map.rivers.len()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.moves >= self.total_moves()
+    }
+
+    pub fn last_mover(&self) -> Option<PunterId> {
+        self.last_mover
+    }
+
+    /// Whose turn it is: punters take turns in order of their ids, starting
+    /// with 0
+    pub fn next_punter(&self) -> PunterId {
+        self.last_mover.map_or(0, |punter| (punter + 1) % self.punters)
+    }
+
+    /// Passes the punter has banked for splurges
+    pub fn credits(&self, punter: PunterId) -> usize {
+        self.credits[punter]
+    }
+
+    /// Options the punter may still buy; none unless options are enabled
+    pub fn options_left(&self, punter: PunterId) -> usize {
+        if self.settings.options {
+            self.mines.len() - self.options_used[punter]
+        } else {
+            0
+        }
+    }
+
+    pub fn futures(&self, punter: PunterId) -> &[Future] {
+        &self.futures[punter]
+    }
+
+    /// Take the futures a punter bet on when it was ready. Each has to run
+    /// from a mine to a site that isn't one.
+    pub fn set_futures(&mut self, punter: PunterId, futures: Vec<Future>) -> Result<(), String> {
+        self.check_punter(punter)?;
+        if !self.settings.futures {
+            return Err("Futures are not enabled".to_string());
+        }
+        if self.moves > 0 {
+            return Err("Futures have to be set before the first move".to_string());
+        }
+        for future in &futures {
+            let target = self.site_index.get(&future.target)
+                .ok_or_else(|| format!("Future to unknown site {}", future.target))?;
+            if !self.map.mines.contains(&future.source) || self.mines.contains(target) {
+                return Err(format!("Future {}-{} must run from a mine to another site",
+                                   future.source, future.target));
+            }
+        }
+        self.futures[punter] = futures;
+        Ok(())
+    }
+
+    /// Apply a move the way the server would, and return the rivers it
+    /// took. An illegal move is rejected and leaves the state as it was;
+    /// the server would count it as a pass. Moves are taken in the order
+    /// they're applied, which is up to the caller: a turn message lists the
+    /// last move of every punter by id, not in the order they were made.
+    pub fn apply(&mut self, next_move: &Move) -> Result<Vec<RiverIdx>, String> {
+        if self.is_over() {
+            return Err("The game is over".to_string());
+        }
+        let taken = match *next_move {
+            Move::pass (protocol::Pass {punter}) => {
+                self.check_punter(punter)?;
+                self.credits[punter] += 1;
+                vec![]
+            }
+            Move::claim (protocol::Claim {punter, source, target}) => {
+                self.check_punter(punter)?;
+                vec![self.take(punter, source, target, false)?]
+            }
+            Move::option (protocol::Claim {punter, source, target}) => {
+                self.check_punter(punter)?;
+                vec![self.take(punter, source, target, true)?]
+            }
+            Move::splurge (protocol::Splurge {punter, ref route}) => {
+                self.check_punter(punter)?;
+                self.splurge(punter, route)?
+            }
+        };
+        self.moves += 1;
+        self.last_mover = Some(next_move.punter());
+        Ok(taken)
+    }
+
+    /// Everyone's score, futures included
+    pub fn scores(&self) -> Vec<i64> {
+        let mut que = VecDeque::with_capacity(self.num_sites());
+        let mut visited = vec![false; self.num_sites()];
+        (0..self.punters).map(|punter| {
+            let mut score = 0;
+            for (mine_idx, mine) in self.mines.iter().enumerate() {
+                score += mine_score(&self.map.rivers, &self.edges, punter, *mine,
+                                    &self.distances[mine_idx], &mut visited, &mut que) as i64;
+                for future in self.futures[punter].iter().filter(|f| f.source == self.map.mines[mine_idx]) {
+                    let target = self.site_index[&future.target];
+                    let dist = self.distances[mine_idx][target];
+                    if dist != usize::MAX {
+                        score += future_score(dist as u64, visited[target]);
+                    }
+                }
+            }
+            score
+        }).collect()
+    }
+
+    fn check_punter(&self, punter: PunterId) -> Result<(), String> {
+        if punter < self.punters {
+            Ok(())
+        } else {
+            Err(format!("No punter {} in a game of {}", punter, self.punters))
+        }
+    }
+
+    fn take(&mut self, punter: PunterId, source: SiteId, target: SiteId,
+            option: bool) -> Result<RiverIdx, String> {
+        if option && self.options_left(punter) == 0 {
+            return Err(format!("Punter {} has no options left", punter));
+        }
+        let ridx = find_river(&self.map.rivers, &self.edges, &self.site_index,
+                              punter, source, target, option)
+            .ok_or_else(|| format!("No river {}-{} left to {}", source, target,
+                                   if option { "option" } else { "claim" }))?;
+        self.map.rivers[ridx].add_owner(punter);
+        if option {
+            self.options_used[punter] += 1;
+        }
+        Ok(ridx)
+    }
+
+    // A route of n rivers costs n - 1 banked passes. Each step claims, or
+    // buys an option where no river is left to claim.
+    fn splurge(&mut self, punter: PunterId, route: &[SiteId]) -> Result<Vec<RiverIdx>, String> {
+        if !self.settings.splurges {
+            return Err("Splurges are not enabled".to_string());
+        }
+        if route.len() < 2 {
+            return Err("A splurge needs at least one river".to_string());
+        }
+        let cost = route.len() - 2;
+        if self.credits[punter] < cost {
+            return Err(format!("Punter {} has {} credits for a splurge costing {}",
+                               punter, self.credits[punter], cost));
+        }
+        let mut taken = vec![];
+        for step in route.windows(2) {
+            let claimable = find_river(&self.map.rivers, &self.edges, &self.site_index,
+                                       punter, step[0], step[1], false).is_some();
+            match self.take(punter, step[0], step[1], !claimable) {
+                Ok(ridx) => taken.push((ridx, !claimable)),
+                Err(e) => {
+                    // Hand back what the route took so far
+                    for &(ridx, option) in &taken {
+                        if option {
+                            self.map.rivers[ridx].renter = None;
+                            self.options_used[punter] -= 1;
+                        } else {
+                            self.map.rivers[ridx].owner = None;
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        self.credits[punter] -= cost;
+        Ok(taken.into_iter().map(|(ridx, _)| ridx).collect())
+    }
+}
+
+fn bin_serializer<S, T>(field: T, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+          T: Serialize
+{
+    serializer.serialize_bytes(&bincode::serialize(&field, bincode::Infinite).unwrap()[..])
+}
+
+fn bin_deserializer<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>,
+          T: DeserializeOwned
+{
+    let v: Vec<u8> = Deserialize::deserialize(deserializer)?;
+    Ok(bincode::deserialize(&v[..]).unwrap())
+}
+
+// The river that a claim, or with `option` an option, between two sites
+// lands on. Maps can have several rivers between the same sites: a claim
+// takes the first free one, and an option the first one somebody else owns
+// and nobody rents yet.
+fn find_river(rivers: &[River], edges: &Adjacency, site_index: &SiteIndex,
+              punter: PunterId, source: SiteId, target: SiteId,
+              option: bool) -> Option<RiverIdx> {
+    let source_idx = *site_index.get(&source)?;
+    let target_idx = *site_index.get(&target)?;
+    edges.between(rivers, source_idx, target_idx).iter()
+        .find(|ridx| {
+            let river = &rivers[**ridx];
+            if option {
+                river.owner.is_some() && river.owner != Some(punter) && river.renter.is_none()
+            } else {
+                river.owner.is_none()
+            }
+        })
+        .cloned()
+}
+
+// What one mine is worth to a punter: the squared distance of every site
+// its rivers connect to the mine. Leaves `visited` marking those sites;
+// `visited` and `que` are scratch space so that scoring allocates once.
+pub(crate) fn mine_score(rivers: &[River], edges: &Adjacency, punter: PunterId,
+                         mine: SiteIdx, distances: &[usize], visited: &mut [bool],
+                         que: &mut VecDeque<SiteIdx>) -> u64 {
+    let mut score = 0;
+    visited.fill(false);
+    visited[mine] = true;
+    que.push_back(mine);
+    while let Some(site_idx) = que.pop_front() {
+        // Only sites on the mine's side of the map are ever reached, but
+        // don't trust the map to be connected
+        let dist = match distances[site_idx] {
+            usize::MAX => 0,
+            dist => dist as u64,
+        };
+        score += dist*dist;
+        for ridx in &edges[site_idx] {
+            let river = &rivers[*ridx];
+            if !river.is_held_by(punter) {
+                continue;
+            }
+            let neighbor = river.other_index(site_idx);
+            if !visited[neighbor] {
+                visited[neighbor] = true;
+                que.push_back(neighbor);
+            }
+        }
+    }
+    score
+}
+
+// A future scores the cube of its length, won if the punter connected it
+// and lost otherwise
+pub(crate) fn future_score(distance: u64, fulfilled: bool) -> i64 {
+    let score = (distance * distance * distance) as i64;
+    if fulfilled { score } else { -score }
+}
@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+use std::ops::Index;
+
+use punter::{River, RiverIdx, SiteId, SiteIdx};
+
+// Ids up to this many times the number of sites get a direct lookup table
+const DENSE_FACTOR: usize = 4;
+
+/// Maps site ids to their position in the map's site list. Ids are whatever
+/// the server picked, so they can be huge and full of gaps: small ids go
+/// through a plain table, anything else through a binary search over the
+/// sorted ids. Unlike a hash map, either way serializes deterministically.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct SiteIndex {
+    // Indexed by id, SiteIdx::MAX where there is no such site
+    table: Vec<SiteIdx>,
+    // Sorted by id; only used if the ids don't fit the table
+    sorted: Vec<(SiteId, SiteIdx)>,
+}
+
+impl SiteIndex {
+    pub(crate) fn new<I>(ids: I) -> SiteIndex
+        where I: Iterator<Item=SiteId>
+    {
+        let mut sorted = ids.enumerate().map(|(idx, id)| (id, idx)).collect::<Vec<_>>();
+        // Keep the first of any duplicate ids
+        sorted.sort_by_key(|&(id, _)| id);
+        sorted.dedup_by_key(|&mut (id, _)| id);
+
+        let max_id = sorted.last().map_or(0, |&(id, _)| id);
+        if max_id / DENSE_FACTOR >= sorted.len() {
+            return SiteIndex { table: vec![], sorted };
+        }
+        let mut table = vec![SiteIdx::MAX; max_id + 1];
+        for (id, idx) in sorted {
+            table[id] = idx;
+        }
+        SiteIndex { table, sorted: vec![] }
+    }
+
+    pub(crate) fn get(&self, id: &SiteId) -> Option<&SiteIdx> {
+        if self.sorted.is_empty() {
+            self.table.get(*id).filter(|idx| **idx != SiteIdx::MAX)
+        } else {
+            self.sorted.binary_search_by_key(id, |&(id, _)| id)
+                .ok()
+                .map(|pos| &self.sorted[pos].1)
+        }
+    }
+}
+
+impl Index<&SiteId> for SiteIndex {
+    type Output = SiteIdx;
+
+    fn index(&self, id: &SiteId) -> &SiteIdx {
+        self.get(id).expect("unknown site id")
+    }
+}
+
+/// The rivers at every site, in compressed sparse row form: the rivers of
+/// site `s` are `rivers[offsets[s]..offsets[s + 1]]`, sorted by the index of
+/// the site at their other end. One allocation for the whole map instead of
+/// one per site.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct Adjacency {
+    offsets: Vec<usize>,
+    rivers: Vec<RiverIdx>,
+}
+
+impl Adjacency {
+    /// Expects the rivers' site indices to be filled in already
+    pub(crate) fn new(sites: usize, rivers: &[River]) -> Adjacency {
+        let mut offsets = vec![0; sites + 1];
+        for river in rivers {
+            offsets[river.source_idx + 1] += 1;
+            offsets[river.target_idx + 1] += 1;
+        }
+        for site in 0..sites {
+            offsets[site + 1] += offsets[site];
+        }
+
+        let mut next = offsets.clone();
+        let mut adjacent = vec![(0, 0); offsets[sites]];
+        for (ridx, river) in rivers.iter().enumerate() {
+            for &(site, neighbor) in &[(river.source_idx, river.target_idx),
+                                       (river.target_idx, river.source_idx)] {
+                adjacent[next[site]] = (neighbor, ridx);
+                next[site] += 1;
+            }
+        }
+        // Parallel rivers end up in river order
+        for site in 0..sites {
+            adjacent[offsets[site]..offsets[site + 1]].sort_unstable();
+        }
+        Adjacency {
+            offsets,
+            rivers: adjacent.into_iter().map(|(_, ridx)| ridx).collect(),
+        }
+    }
+
+    /// All rivers between two sites, in river order: more than one if the
+    /// map has parallel rivers
+    pub(crate) fn between(&self, rivers: &[River], source: SiteIdx,
+                          target: SiteIdx) -> &[RiverIdx] {
+        let adjacent = &self[source];
+        let other = |ridx: &RiverIdx| rivers[*ridx].other_index(source);
+        let begin = adjacent.partition_point(|ridx| other(ridx) < target);
+        let end = adjacent.partition_point(|ridx| other(ridx) <= target);
+        &adjacent[begin..end]
+    }
+
+    /// Length of the shortest route from `from` to every site, usize::MAX
+    /// for sites on another island of the map. All rivers have the same
+    /// length, so a breadth-first search visits every site once.
+    pub(crate) fn distances(&self, rivers: &[River], from: SiteIdx) -> Vec<usize> {
+        let mut dists = vec![usize::MAX; self.offsets.len() - 1];
+        let mut que = VecDeque::new();
+        dists[from] = 0;
+        que.push_back(from);
+        while let Some(site) = que.pop_front() {
+            for ridx in &self[site] {
+                let neighbor = rivers[*ridx].other_index(site);
+                if dists[neighbor] == usize::MAX {
+                    dists[neighbor] = dists[site] + 1;
+                    que.push_back(neighbor);
+                }
+            }
+        }
+        dists
+    }
+}
+
+impl Index<SiteIdx> for Adjacency {
+    type Output = [RiverIdx];
+
+    fn index(&self, site: SiteIdx) -> &[RiverIdx] {
+        &self.rivers[self.offsets[site]..self.offsets[site + 1]]
+    }
+}
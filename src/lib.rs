@@ -4,8 +4,20 @@ extern crate serde_derive;
 extern crate getopts;
 extern crate bufstream;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 extern crate rand;
 
+#[macro_use]
+pub mod logging;
 pub mod punter;
+pub mod game;
 pub mod protocol;
+pub mod opponent;
+pub mod planner;
+pub mod analysis;
+pub mod render;
+pub mod player;
+pub mod arena;
+mod graph;
+mod endgame;
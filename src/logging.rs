@@ -0,0 +1,223 @@
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use protocol;
+
+/// Read by `init` when no spec is given on the command line
+pub const ENV_VAR: &str = "PUNTER_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("Unknown log level {}, expected one of off, error, warn, \
+                              info, debug or trace", s)),
+        }
+    }
+}
+
+/// Which records to keep: a default level and per-target overrides, written
+/// like `warn,search=debug,turn=off`. Targets name what a record is about,
+/// e.g. `game`, `turn` or `search`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogSpec {
+    // None for off
+    default: Option<Level>,
+    targets: Vec<(String, Option<Level>)>,
+}
+
+impl LogSpec {
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        let max = self.targets.iter()
+            .find(|(t, _)| t == target)
+            .map_or(self.default, |&(_, max)| max);
+        max.is_some_and(|max| level <= max)
+    }
+}
+
+fn parse_level(s: &str) -> Result<Option<Level>, String> {
+    if s == "off" {
+        Ok(None)
+    } else {
+        s.parse().map(Some)
+    }
+}
+
+impl FromStr for LogSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogSpec, String> {
+        let mut spec = LogSpec {
+            default: Some(Level::Info),
+            targets: vec![],
+        };
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.find('=') {
+                Some(eq) => {
+                    let level = parse_level(&part[eq + 1..])?;
+                    spec.targets.push((part[..eq].to_string(), level));
+                }
+                None => spec.default = parse_level(part)?,
+            }
+        }
+        Ok(spec)
+    }
+}
+
+struct Logger {
+    spec: LogSpec,
+    out: Box<dyn Write + Send>,
+}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+/// Set up logging for the whole process. A `spec` from the command line wins
+/// over `PUNTER_LOG`, which wins over `default`. Records go to `file` if
+/// given and to stderr otherwise, never to stdout: the offline protocol runs
+/// over it. Until this is called, only errors and warnings are written, to
+/// stderr.
+pub fn init(spec: Option<&str>, default: &str, file: Option<&str>) -> Result<(), String> {
+    let spec = match spec {
+        Some(spec) => spec.to_string(),
+        None => env::var(ENV_VAR).unwrap_or_else(|_| default.to_string()),
+    };
+    let spec = spec.parse()?;
+    let out: Box<dyn Write + Send> = match file {
+        Some(path) => Box::new(File::create(path)
+            .map_err(|e| format!("Could not create log file {}: {}", path, e))?),
+        None => Box::new(io::stderr()),
+    };
+    *LOGGER.lock().unwrap() = Some(Logger { spec, out });
+    Ok(())
+}
+
+pub fn enabled(level: Level, target: &str) -> bool {
+    match *LOGGER.lock().unwrap() {
+        Some(ref logger) => logger.spec.enabled(level, target),
+        None => level <= Level::Warn,
+    }
+}
+
+fn write_line(line: &str) {
+    let mut logger = LOGGER.lock().unwrap();
+    // Losing a log line is better than losing the game
+    let _ = match *logger {
+        Some(ref mut logger) => writeln!(logger.out, "{}", line)
+            .and_then(|_| logger.out.flush()),
+        None => writeln!(io::stderr(), "{}", line),
+    };
+}
+
+/// Write a plain message; use the `error!` to `trace!` macros instead
+pub fn log(level: Level, target: &str, args: fmt::Arguments) {
+    if enabled(level, target) {
+        write_line(&format!("[{} {}] {}", level.name(), target, args));
+    }
+}
+
+/// Write a structured record as a single JSON object, with the level and
+/// target added to the record's own fields
+pub fn record<T>(level: Level, target: &str, record: &T)
+    where T: Serialize
+{
+    if !enabled(level, target) {
+        return;
+    }
+    let mut fields = match serde_json::to_value(record) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(value) => {
+            let mut fields = serde_json::Map::new();
+            fields.insert("record".to_string(), value);
+            fields
+        }
+        Err(e) => {
+            log(Level::Error, target, format_args!("Could not encode log record: {}", e));
+            return;
+        }
+    };
+    fields.insert("level".to_string(), Value::from(level.name()));
+    fields.insert("target".to_string(), Value::from(target));
+    write_line(&Value::Object(fields).to_string());
+}
+
+/// What we did on one turn, logged under the `turn` target
+#[derive(Serialize, Debug)]
+pub struct TurnRecord<'a> {
+    pub turn: usize,
+    #[serde(rename = "move")]
+    pub next_move: &'a protocol::Move,
+    pub budget_ms: f64,
+    pub elapsed_ms: f64,
+    // Only for tree searches
+    pub iterations: Option<usize>,
+    pub peak_nodes: Option<usize>,
+}
+
+#[macro_export]
+macro_rules! error {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Error, $target, format_args!($($arg)+))
+    }
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Warn, $target, format_args!($($arg)+))
+    }
+}
+
+#[macro_export]
+macro_rules! info {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Info, $target, format_args!($($arg)+))
+    }
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Debug, $target, format_args!($($arg)+))
+    }
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Trace, $target, format_args!($($arg)+))
+    }
+}
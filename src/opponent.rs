@@ -0,0 +1,84 @@
+use punter::{PunterId, RiverIdx};
+
+// Don't trust a model until we've seen this many moves from that punter
+const MIN_OBSERVED_MOVES: usize = 3;
+
+/// A single move seen in a turn message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ObservedMove {
+    pub turn: usize,
+    pub punter: PunterId,
+    // Rivers the move took: none for passes, the whole route for splurges
+    pub rivers: Vec<RiverIdx>,
+}
+
+/// What we've learned about one punter from the moves it played
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpponentModel {
+    pub moves: usize,
+    pub passes: usize,
+
+    // Claims that extended the punter's own network or started at a mine;
+    // a random player hardly ever makes these.
+    pub connected_claims: usize,
+
+    // For every mine (in map order), how many claims ended up connected to it
+    pub mine_claims: Vec<usize>,
+
+    pub last_turn: Option<usize>,
+}
+
+impl OpponentModel {
+    pub fn new(mines: usize) -> OpponentModel {
+        OpponentModel {
+            mine_claims: vec![0; mines],
+            ..Default::default()
+        }
+    }
+
+    pub fn observe(&mut self, turn: usize, claimed: bool, connected: bool,
+                   mines: &[usize]) {
+        self.moves += 1;
+        self.last_turn = Some(turn);
+        if !claimed {
+            self.passes += 1;
+            return;
+        }
+        if connected {
+            self.connected_claims += 1;
+        }
+        for mine in mines {
+            self.mine_claims[*mine] += 1;
+        }
+    }
+
+    /// Whether we've seen enough moves for the statistics to mean anything
+    pub fn is_informed(&self) -> bool {
+        self.moves >= MIN_OBSERVED_MOVES
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        if self.moves == 0 {
+            return 0.;
+        }
+        self.passes as f64 / self.moves as f64
+    }
+
+    /// Fraction of claims that extended the punter's network: close to 1 for
+    /// greedy players, close to 0 for random ones.
+    pub fn greediness(&self) -> f64 {
+        let claims = self.moves - self.passes;
+        if claims == 0 {
+            return 0.;
+        }
+        self.connected_claims as f64 / claims as f64
+    }
+
+    /// Index of the mine the punter has been building around the most
+    pub fn favourite_mine(&self) -> Option<usize> {
+        self.mine_claims.iter().enumerate()
+            .filter(|&(_, claims)| *claims > 0)
+            .max_by_key(|&(_, claims)| *claims)
+            .map(|(mine, _)| mine)
+    }
+}
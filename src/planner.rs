@@ -0,0 +1,168 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use punter::{Punter, PunterId, RiverIdx, SiteId, SiteIdx};
+
+// Far-away sites worth pulling into the tree on top of the mines
+const HIGH_VALUE_SITES: usize = 2;
+// Stop growing the tree after connecting this many terminals
+const MAX_TERMINALS: usize = 16;
+
+/// Rivers to claim to link the mines (and a few valuable sites) into a single
+/// network of ours: an approximate Steiner tree over rivers that are free or
+/// already ours, grown with the shortest-path heuristic. Rivers claimed by
+/// anyone else are never part of a plan, so recomputing the plan after every
+/// turn routes around opponents' claims.
+#[derive(Debug, Default)]
+pub struct Plan {
+    // Free rivers, in the order the tree grew; each path starts at the
+    // tree's side
+    pub rivers: Vec<RiverIdx>,
+
+    // Sites the plan connects that our network doesn't reach yet
+    pub terminals: Vec<SiteId>,
+}
+
+impl Plan {
+    pub fn new(punter: &Punter) -> Plan {
+        let me = punter.id();
+        let rivers = &punter.game.map.rivers;
+        let mut in_tree = match initial_tree(punter, me) {
+            Some(in_tree) => in_tree,
+            None => return Plan::default(),
+        };
+        let mut terminals = unconnected_mines(punter, &in_tree);
+        terminals.extend(high_value_sites(punter, &in_tree));
+
+        let mut plan = Plan::default();
+        while plan.terminals.len() < MAX_TERMINALS {
+            let (target, path) = match shortest_path_to(punter, &in_tree, &terminals) {
+                Some(found) => found,
+                None => break,
+            };
+            terminals.retain(|site| *site != target);
+            plan.terminals.push(punter.game.map.sites[target].id);
+            for ridx in path {
+                let river = &rivers[ridx];
+                for site in &[river.source_idx, river.target_idx] {
+                    if !in_tree[*site] {
+                        // Pull in whatever part of our network the path touches
+                        for reached in punter.reachable_sites(rivers, me, *site) {
+                            in_tree[reached] = true;
+                        }
+                    }
+                }
+                if river.owner.is_none() {
+                    plan.rivers.push(ridx);
+                }
+            }
+        }
+        plan
+    }
+}
+
+/// Sites a tree for `me` starts from: the components of `me`'s network that
+/// hold a mine, or the mine with the most free rivers if `me` hasn't started
+/// yet. None if there is nowhere left to build.
+pub(crate) fn initial_tree(punter: &Punter, me: PunterId) -> Option<Vec<bool>> {
+    let rivers = &punter.game.map.rivers;
+    let mine_sites = punter.game.map.mines.iter()
+        .map(|mine| punter.game.site_index[mine])
+        .collect::<Vec<_>>();
+    let mut in_tree = vec![false; punter.game.map.sites.len()];
+    let components = punter.components(rivers, me);
+    for mine in &mine_sites {
+        if punter.site_has_river(rivers, *mine, me) {
+            for (site, component) in components.iter().enumerate() {
+                if *component == components[*mine] {
+                    in_tree[site] = true;
+                }
+            }
+        }
+    }
+    if !in_tree.iter().any(|t| *t) {
+        let free_rivers = |site: SiteIdx| {
+            punter.game.edges[site].iter().filter(|r| rivers[**r].owner.is_none()).count()
+        };
+        let start = mine_sites.iter().cloned()
+            .filter(|site| free_rivers(*site) > 0)
+            .max_by_key(|site| (free_rivers(*site), Reverse(*site)))?;
+        in_tree[start] = true;
+    }
+    Some(in_tree)
+}
+
+pub(crate) fn unconnected_mines(punter: &Punter, in_tree: &[bool]) -> Vec<SiteIdx> {
+    punter.game.map.mines.iter()
+        .map(|mine| punter.game.site_index[mine])
+        .filter(|site| !in_tree[*site])
+        .collect()
+}
+
+// Sites far from every mine, which score the most once connected
+pub(crate) fn high_value_sites(punter: &Punter, in_tree: &[bool]) -> Vec<SiteIdx> {
+    let value = |site: SiteIdx| -> u64 {
+        punter.game.distances.iter()
+            .map(|dists| dists[site])
+            .filter(|dist| *dist != usize::MAX)
+            .map(|dist| (dist as u64) * (dist as u64))
+            .sum()
+    };
+    let mut sites = (0..in_tree.len())
+        .filter(|site| !in_tree[*site] && !punter.is_mine(*site))
+        .collect::<Vec<_>>();
+    sites.sort_by_key(|site| (Reverse(value(*site)), *site));
+    sites.truncate(HIGH_VALUE_SITES);
+    sites
+}
+
+// 0-1 BFS from the tree to the nearest terminal, over rivers we own (cost 0)
+// or could claim (cost 1). Returns the terminal and the path's rivers from
+// the tree outwards.
+pub(crate) fn shortest_path_to(punter: &Punter, in_tree: &[bool],
+                               terminals: &[SiteIdx]) -> Option<(SiteIdx, Vec<RiverIdx>)> {
+    let me = punter.id();
+    let rivers = &punter.game.map.rivers;
+    let mut dist = vec![usize::MAX; in_tree.len()];
+    let mut via: Vec<Option<RiverIdx>> = vec![None; in_tree.len()];
+    let mut que = VecDeque::new();
+    for (site, in_tree) in in_tree.iter().enumerate() {
+        if *in_tree {
+            dist[site] = 0;
+            que.push_back(site);
+        }
+    }
+    while let Some(site) = que.pop_front() {
+        if terminals.contains(&site) {
+            let mut path = vec![];
+            let mut cur = site;
+            while let Some(ridx) = via[cur] {
+                path.push(ridx);
+                cur = rivers[ridx].other_index(cur);
+            }
+            path.reverse();
+            return Some((site, path));
+        }
+        for ridx in &punter.game.edges[site] {
+            let river = &rivers[*ridx];
+            let cost = if river.is_held_by(me) {
+                0
+            } else if river.owner.is_none() {
+                1
+            } else {
+                continue;
+            };
+            let neighbor = river.other_index(site);
+            if dist[site] + cost < dist[neighbor] {
+                dist[neighbor] = dist[site] + cost;
+                via[neighbor] = Some(*ridx);
+                if cost == 0 {
+                    que.push_front(neighbor);
+                } else {
+                    que.push_back(neighbor);
+                }
+            }
+        }
+    }
+    None
+}
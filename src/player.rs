@@ -0,0 +1,198 @@
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use protocol::{self, Move, Score};
+
+// The official server gives bots ten seconds to set up; we give them as long
+// to take in the end of the game
+const SETUP_LIMIT: Duration = Duration::from_secs(10);
+const STOP_LIMIT: Duration = Duration::from_secs(10);
+// How often to check whether a bot that has replied has also exited
+const EXIT_POLL: Duration = Duration::from_millis(5);
+use punter::{Input, Punter, PunterType, TimeBudget};
+
+/// Anyone who can take a seat in a local game: one of our own AIs, or
+/// another team's bot. Errors are reported as messages; the game carries on
+/// without the move.
+pub trait Player {
+    /// Called once, before the first turn
+    fn setup(&mut self, input: &Input) -> Result<(), String>;
+
+    /// Take in the last move of every punter and pick our next one within
+    /// `budget`. Zero means no limit is enforced; our own AIs then take as
+    /// long as the official server's one-second timeout allows.
+    fn play(&mut self, moves: &[Move], budget: Duration) -> Result<Move, String>;
+
+    /// The game is over
+    fn stop(&mut self, moves: &[Move], scores: &[Score]) -> Result<(), String>;
+}
+
+/// One of our AIs, played in process
+pub struct Builtin {
+    ai: PunterType,
+    punter: Option<Punter>,
+}
+
+impl Builtin {
+    pub fn new(ai: PunterType) -> Builtin {
+        Builtin { ai, punter: None }
+    }
+}
+
+impl Player for Builtin {
+    fn setup(&mut self, input: &Input) -> Result<(), String> {
+        self.punter = Some(Punter::new(input.clone(), self.ai));
+        Ok(())
+    }
+
+    fn play(&mut self, moves: &[Move], budget: Duration) -> Result<Move, String> {
+        let begin_time = Instant::now();
+        let punter = self.punter.as_mut().ok_or("Asked to play before setup")?;
+        punter.process_turn(moves);
+        // MCTS without any time would fall back to greedy
+        let budget = if budget > Duration::from_secs(0) {
+            budget
+        } else {
+            TimeBudget::new(1).duration()
+        };
+        Ok(punter.make_move(begin_time, budget))
+    }
+
+    fn stop(&mut self, _moves: &[Move], _scores: &[Score]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A bot speaking the official offline protocol, whatever it's written in.
+/// Like the official server, we start the program afresh for every message
+/// and keep its state, which is opaque to us, in between.
+pub struct External {
+    program: String,
+    args: Vec<String>,
+    state: Option<Value>,
+}
+
+impl External {
+    pub fn new(program: String, args: Vec<String>) -> External {
+        External { program, args, state: None }
+    }
+
+    /// Parse a command line like `python3 bot.py --fast`. Arguments are
+    /// split on whitespace, without any quoting.
+    pub fn from_command(command: &str) -> Option<External> {
+        let mut words = command.split_whitespace().map(String::from);
+        let program = words.next()?;
+        Some(External::new(program, words.collect()))
+    }
+
+    // Kept rather than taken, so a step that goes wrong doesn't lose it
+    fn state(&self) -> Result<Value, String> {
+        self.state.clone().ok_or_else(|| "No state from an earlier step".to_string())
+    }
+
+    // One run of the bot: handshake, our message, and its reply if one is
+    // expected. A bot that hasn't replied and exited within `limit` gets
+    // killed.
+    fn exchange<T, R>(&self, msg: &T, reply: bool,
+                      limit: Option<Duration>) -> Result<Option<R>, String>
+        where T: Serialize,
+              R: DeserializeOwned + Send + 'static
+    {
+        let msg = serde_json::to_value(msg).map_err(|e| e.to_string())?;
+        let deadline = limit.map(|limit| Instant::now() + limit);
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not start {}: {}", self.program, e))?;
+        let mut writer = child.stdin.take().unwrap();
+        let mut reader = BufReader::new(child.stdout.take().unwrap());
+        // Talk to the bot on a thread of its own, so we can stop waiting
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut step = || -> Result<Option<R>, String> {
+                let handshake: protocol::HandshakeP = protocol::read_message(&mut reader)
+                    .map_err(|e| format!("Bad handshake: {}", e))?;
+                protocol::write_message(&mut writer, &protocol::HandshakeS { you: handshake.me })
+                    .and_then(|_| protocol::write_message(&mut writer, &msg))
+                    .map_err(|e| e.to_string())?;
+                if reply {
+                    protocol::read_message(&mut reader).map(Some)
+                } else {
+                    Ok(None)
+                }
+            };
+            // Nobody is listening any more if the bot was too slow
+            let _ = sender.send(step());
+            // Dropping our end of the pipes lets a bot that's still reading
+            // exit
+        });
+        let result = match limit {
+            Some(limit) => receiver.recv_timeout(limit),
+            None => receiver.recv().map_err(RecvTimeoutError::from),
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                child.kill().map_err(|e| e.to_string())?;
+                Err(format!("No reply within {:?}", limit.unwrap()))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err("Lost the bot's reply".to_string()),
+        };
+        let status = match deadline {
+            Some(deadline) => loop {
+                if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    child.kill().map_err(|e| e.to_string())?;
+                    break child.wait().map_err(|e| e.to_string())?;
+                }
+                thread::sleep(EXIT_POLL);
+            },
+            None => child.wait().map_err(|e| e.to_string())?,
+        };
+        match result {
+            Err(e) => Err(format!("{} ({})", e, status)),
+            Ok(reply) => Ok(reply),
+        }
+    }
+}
+
+impl Player for External {
+    fn setup(&mut self, input: &Input) -> Result<(), String> {
+        let msg: protocol::OfflineInput<Value> = protocol::OfflineInput::Setup (input.clone());
+        let ready: protocol::OfflineReadyP<Value> = self.exchange(&msg, true, Some(SETUP_LIMIT))?.unwrap();
+        self.state = Some(ready.state);
+        Ok(())
+    }
+
+    fn play(&mut self, moves: &[Move], budget: Duration) -> Result<Move, String> {
+        let msg = protocol::OfflineInput::Turn (protocol::OfflineTurn {
+            turn: protocol::Moves { moves: moves.to_vec() },
+            state: self.state()?,
+        });
+        // Late moves don't count, so don't wait for them
+        let limit = if budget > Duration::from_secs(0) { Some(budget) } else { None };
+        let reply: protocol::OfflineMove<Value> = self.exchange(&msg, true, limit)?.unwrap();
+        let (next_move, state) = reply.into_parts();
+        self.state = Some(state);
+        Ok(next_move)
+    }
+
+    fn stop(&mut self, moves: &[Move], scores: &[Score]) -> Result<(), String> {
+        let msg = protocol::OfflineInput::Stop (protocol::OfflineStop {
+            stop: protocol::MovesScores { moves: moves.to_vec(), scores: scores.to_vec() },
+            state: self.state()?,
+        });
+        self.exchange::<_, Value>(&msg, false, Some(STOP_LIMIT)).map(|_| ())
+    }
+}
@@ -1,8 +1,42 @@
 #![allow(non_camel_case_types)]
+use std::io::{self, BufRead, Read, Write};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::{self, DeserializeOwned};
+use serde_json::{self, Map, Value};
+
 use punter::PunterId;
 use punter::SiteId;
 use punter::Punter;
-use punter::Input;
+use punter::{Input, InputMap, Settings};
+
+/// Write a message with its length in front, the way both protocols frame
+/// them
+pub fn write_message<W, T>(writer: &mut W, msg: &T) -> io::Result<()>
+    where W: Write,
+          T: Serialize + ?Sized
+{
+    let msg_str = serde_json::to_string(msg)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    writer.write_all(format!("{}:{}", msg_str.len(), msg_str).as_bytes())?;
+    writer.flush()
+}
+
+/// Read a message written by `write_message`
+pub fn read_message<R, T>(reader: &mut R) -> Result<T, String>
+    where R: BufRead,
+          T: DeserializeOwned
+{
+    let mut buf = vec![];
+    reader.read_until(b':', &mut buf).map_err(|e| e.to_string())?;
+    if buf.pop() != Some(b':') {
+        return Err("Connection closed before the next message".to_string());
+    }
+    let len = String::from_utf8(buf).ok()
+        .and_then(|len| len.trim().parse::<u64>().ok())
+        .ok_or("Invalid message length")?;
+    serde_json::from_reader(reader.take(len)).map_err(|e| e.to_string())
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct HandshakeP {
@@ -33,7 +67,7 @@ pub enum TurnS {
     timeout ( f64 ),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Move {
     claim (Claim),
 
@@ -44,42 +78,129 @@ pub enum Move {
     option (Claim),
 }
 
+impl Move {
+    /// Who made the move
+    pub fn punter(&self) -> PunterId {
+        match *self {
+            Move::claim (ref claim) | Move::option (ref claim) => claim.punter,
+            Move::pass (ref pass) => pass.punter,
+            Move::splurge (ref splurge) => splurge.punter,
+        }
+    }
+}
+
+/// A whole game as one punter saw it: the setup, then the moves of every
+/// turn up to and including the ones in the stop message
 #[derive(Serialize, Deserialize, Debug)]
+pub struct GameRecord {
+    pub setup: Input,
+    pub turns: Vec<Vec<Move>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Score {
-    punter: PunterId,
-    score: isize,
+    pub punter: PunterId,
+    pub score: isize,
 }
 
 
 
 
+// The offline messages carry the state of whoever is playing: ours is a
+// Punter, other teams' bots have their own, which we only pass along as JSON
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineReadyP {
+pub struct OfflineReadyP<S = Punter> {
     pub ready: PunterId,
-    pub state: Punter,
+    pub state: S,
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+// Deserialized by hand below: the message kind is picked by key rather than
+// by trying each variant in turn, so errors name the offending field.
+#[derive(Serialize, Debug)]
 #[serde(untagged)]
-pub enum OfflineInput {
+pub enum OfflineInput<S = Punter> {
     Setup (Input),
 
-    Turn (OfflineTurn),
+    Turn (OfflineTurn<S>),
 
-    Stop (OfflineStop),
+    Stop (OfflineStop<S>),
 
     Timeout {
         timeout: f64,
     }
 }
 
+impl<'de, S: DeserializeOwned> Deserialize<'de> for OfflineInput<S> {
+    fn deserialize<D>(deserializer: D) -> Result<OfflineInput<S>, D::Error>
+        where D: Deserializer<'de>
+    {
+        let mut msg = Map::deserialize(deserializer)?;
+        if msg.contains_key("move") {
+            Ok(OfflineInput::Turn (OfflineTurn {
+                turn: take_field(&mut msg, "move", "move")?,
+                state: take_field(&mut msg, "state", "move")?,
+            }))
+        } else if msg.contains_key("stop") {
+            Ok(OfflineInput::Stop (OfflineStop {
+                stop: take_field(&mut msg, "stop", "stop")?,
+                state: take_field(&mut msg, "state", "stop")?,
+            }))
+        } else if msg.contains_key("timeout") {
+            Ok(OfflineInput::Timeout {
+                timeout: take_field(&mut msg, "timeout", "timeout")?,
+            })
+        } else if msg.contains_key("map") {
+            serde_json::from_value(Value::Object(msg.clone()))
+                .map(OfflineInput::Setup)
+                .map_err(|e| setup_error(&mut msg, e))
+        } else {
+            let keys = msg.keys().cloned().collect::<Vec<_>>();
+            Err(de::Error::custom(format!(
+                "unrecognized offline message with fields {:?}, \
+                 expected setup, move, stop or timeout", keys)))
+        }
+    }
+}
+
+fn take_field<T, E>(msg: &mut Map<String, Value>, field: &str, kind: &str) -> Result<T, E>
+    where T: DeserializeOwned,
+          E: de::Error
+{
+    let value = msg.remove(field).ok_or_else(|| E::custom(format!(
+        "missing field `{}` in offline {} message", field, kind)))?;
+    serde_json::from_value(value).map_err(|e| E::custom(format!(
+        "invalid field `{}` in offline {} message: {}", field, kind, e)))
+}
+
+/// Find which field of a setup message is broken, falling back to the
+/// original error if every field decodes on its own.
+fn setup_error<E: de::Error>(msg: &mut Map<String, Value>, error: serde_json::Error) -> E {
+    fn check<T: DeserializeOwned, E: de::Error>(msg: &mut Map<String, Value>,
+                                                field: &str) -> Result<(), E> {
+        take_field::<T, E>(msg, field, "setup").map(|_| ())
+    }
+    let result = check::<PunterId, E>(msg, "punter")
+        .and_then(|_| check::<PunterId, E>(msg, "punters"))
+        .and_then(|_| check::<InputMap, E>(msg, "map"))
+        .and_then(|_| if msg.contains_key("settings") {
+            check::<Settings, E>(msg, "settings")
+        } else {
+            Ok(())
+        });
+    match result {
+        Err(e) => e,
+        Ok(()) => E::custom(format!("invalid offline setup message: {}", error)),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineTurn {
+pub struct OfflineTurn<S = Punter> {
     // move is a reserved keyword
     #[serde(rename = "move")]
     pub turn: Moves,
-    pub state: Punter,
+    pub state: S,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -88,9 +209,9 @@ pub struct Moves {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineStop {
+pub struct OfflineStop<S = Punter> {
     pub stop: MovesScores,
-    pub state: Punter,
+    pub state: S,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -101,23 +222,44 @@ pub struct MovesScores {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
-pub enum OfflineMove {
-    Claim (OfflineClaim),
+pub enum OfflineMove<S = Punter> {
+    Claim (OfflineClaim<S>),
+
+    Pass (OfflinePass<S>),
 
-    Pass (OfflinePass),
+    Splurge (OfflineSplurge<S>),
+
+    Option (OfflineOption<S>),
+}
 
-    Splurge (OfflineSplurge),
+impl<S> OfflineMove<S> {
+    pub fn new(next_move: Move, state: S) -> OfflineMove<S> {
+        match next_move {
+            Move::claim (claim) => OfflineMove::Claim (OfflineClaim { claim, state }),
+            Move::pass (pass) => OfflineMove::Pass (OfflinePass { pass, state }),
+            Move::splurge (splurge) => OfflineMove::Splurge (OfflineSplurge { splurge, state }),
+            Move::option (option) => OfflineMove::Option (OfflineOption { option, state }),
+        }
+    }
 
-    Option (OfflineOption),
+    /// Split into the move and the state to hand back on the next turn
+    pub fn into_parts(self) -> (Move, S) {
+        match self {
+            OfflineMove::Claim (OfflineClaim { claim, state }) => (Move::claim (claim), state),
+            OfflineMove::Pass (OfflinePass { pass, state }) => (Move::pass (pass), state),
+            OfflineMove::Splurge (OfflineSplurge { splurge, state }) => (Move::splurge (splurge), state),
+            OfflineMove::Option (OfflineOption { option, state }) => (Move::option (option), state),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineClaim {
+pub struct OfflineClaim<S = Punter> {
     pub claim: Claim,
-    pub state: Punter,
+    pub state: S,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Claim {
     pub punter: PunterId,
     pub source: SiteId,
@@ -125,30 +267,36 @@ pub struct Claim {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflinePass {
+pub struct OfflinePass<S = Punter> {
     pub pass: Pass,
-    pub state: Punter,
+    pub state: S,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Pass {
     pub punter: PunterId,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineSplurge {
+pub struct OfflineSplurge<S = Punter> {
     pub splurge: Splurge,
-    pub state: Punter,
+    pub state: S,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Splurge {
     pub punter: PunterId,
     pub route: Vec<SiteId>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Future {
+    pub source: SiteId,
+    pub target: SiteId,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct OfflineOption {
+pub struct OfflineOption<S = Punter> {
     pub option: Claim,
-    pub state: Punter,
+    pub state: S,
 }
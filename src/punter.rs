@@ -1,80 +1,106 @@
-extern crate bincode;
-extern crate serde_bytes;
 
 use std::collections::{HashSet, HashMap, VecDeque};
-use rand::{thread_rng};
+use rand::{thread_rng, ThreadRng};
 use std::time::{Duration, Instant};
-use std::f64::NEG_INFINITY;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::iter;
+use std::cmp::Reverse;
+use std::str::FromStr;
 use rand::Rng;
 use std::rc::Rc;
-use std::rc::Weak;
 use std::cell::RefCell;
-use serde::{Serialize, Serializer, Deserialize, Deserializer};
-use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 
 use protocol;
+use opponent::{ObservedMove, OpponentModel};
+use planner::{self, Plan};
+use analysis::Criticality;
+use endgame;
+use game::{self, GameState};
 
 pub type PunterId = usize;
 pub type SiteId = usize;
 pub type SiteIdx = usize;
 pub type RiverIdx = usize;
 
-type SiteIndex = HashMap<SiteId, SiteIdx>;
-type EdgeMatrix = Vec<Vec<RiverIdx>>;
-type ShortestPathsMap = Vec<Vec<usize>>;
-
-const SIMULATION_DEPTH: usize = 100;
 const AVAILABLE_RADIUS: Option<usize> = Some(2);
+const TIMEOUT_MARGIN_MS: u64 = 100;
+// Below this many iterations the MCTS statistics are noise; play greedy instead
+const MIN_MCTS_ITERATIONS: usize = 50;
+// Sites a rollout looks at for a river that extends an opponent's network
+// before giving up and playing at random
+const FRONTIER_TRIES: usize = 8;
+// Moves kept in the history, which travels with the offline state
+const HISTORY_LEN: usize = 64;
+// Time set aside per search node for writing out the tree when asked to,
+// about twice what it takes in a release build
+const JSON_DUMP_NS_PER_NODE: u64 = 20_000;
+const DOT_DUMP_NS_PER_NODE: u64 = 4_000;
+// Time set aside for writing out the offline state, per river and per site
+// and mine, about twice what it takes in a release build
+const STATE_NS_PER_ITEM: u64 = 300;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Input {
-    punter: PunterId,
-    punters: PunterId,
-    map: InputMap,
+    pub(crate) punter: PunterId,
+    pub(crate) punters: PunterId,
+    pub(crate) map: InputMap,
 
     #[serde(default)]
-    settings: Settings,
+    pub(crate) settings: Settings,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InputMap {
-    sites: Vec<Site>,
-    rivers: Vec<River>,
-    mines: Vec<SiteId>,
+    pub(crate) sites: Vec<Site>,
+    pub(crate) rivers: Vec<River>,
+    pub(crate) mines: Vec<SiteId>,
+
+    // Whatever else the server sent with the map, passed through as is
+    #[serde(flatten)]
+    pub(crate) extra: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Settings {
     #[serde(default)]
-    futures: bool,
+    pub(crate) futures: bool,
     #[serde(default)]
-    splurges: bool,
+    pub(crate) splurges: bool,
     #[serde(default)]
-    options: bool,
+    pub(crate) options: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Site {
-    id: SiteId,
+    pub(crate) id: SiteId,
+
+    // Where to draw the site; the official maps have them
+    #[serde(default)]
+    pub(crate) x: Option<f64>,
+    #[serde(default)]
+    pub(crate) y: Option<f64>,
+
+    #[serde(flatten)]
+    pub(crate) extra: Map<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct River {
-    source: SiteId,
-    target: SiteId,
+    pub(crate) source: SiteId,
+    pub(crate) target: SiteId,
 
     #[serde(default)]
-    owner: Option<PunterId>,
+    pub(crate) owner: Option<PunterId>,
 
     #[serde(default)]
-    renter: Option<PunterId>,
+    pub(crate) renter: Option<PunterId>,
 
     #[serde(default)]
-    source_idx: SiteIdx,
+    pub(crate) source_idx: SiteIdx,
     #[serde(default)]
-    target_idx: SiteIdx,
+    pub(crate) target_idx: SiteIdx,
 }
 
 impl River {
@@ -88,6 +114,10 @@ impl River {
         }
     }
 
+    pub fn is_held_by(&self, punter: PunterId) -> bool {
+        self.owner == Some(punter) || self.renter == Some(punter)
+    }
+
     pub fn other_index(&self, site: SiteIdx) -> SiteId {
         if site == self.source_idx {
             self.target_idx
@@ -99,198 +129,376 @@ impl River {
 }
 
 impl Input {
-    // Helper function used by the binary search and sort
-    fn river_other_index(&self, river: RiverIdx, site: SiteIdx) -> SiteId {
-        self.map.rivers[river].other_index(site)
-    }
-
-    fn index_rivers(&mut self, site_index: &SiteIndex) {
-        for river in &mut self.map.rivers {
-            river.source_idx = site_index[&river.source];
-            river.target_idx = site_index[&river.target];
-        }
-    }
-
-    // Construct the incidence matrix for the graph
-    fn compute_edges(&self, site_index: &SiteIndex) -> EdgeMatrix {
-        let mut edges = vec![vec![]; site_index.len()];
-        for (idx, ref river) in self.map.rivers.iter().enumerate() {
-            edges[river.source_idx].push(idx);
-            edges[river.target_idx].push(idx);
-        }
-        // Sort the edges of each site by the id of the other side
-        for (idx, ref mut site_edges) in edges.iter_mut().enumerate() {
-            site_edges.sort_by_key(|river| self.river_other_index(*river, idx));
-        }
-        edges
-    }
-
-    fn compute_shortest_paths(&self, edges: &EdgeMatrix,
-                              site_index: &SiteIndex) -> ShortestPathsMap {
-        // Since all edges have the same length of 1,
-        // we can compute the shortest path using a simple
-        // breadth-first search algorithm; for every mine,
-        // we visit all sites exactly once.
-        let mut shortest_paths = vec![vec![usize::max_value(); self.map.sites.len()]; self.map.mines.len()];
-        let mut que: VecDeque<SiteId> = VecDeque::with_capacity(self.map.sites.len());
-        for (mine, ref mut mine_dists) in self.map.mines.iter().zip(shortest_paths.iter_mut()) {
-            let mine_idx = site_index[mine];
-            mine_dists[mine_idx] = 0;
-            que.push_back(mine_idx);
-            while let Some(site_idx) = que.pop_front() {
-                let site_dist = mine_dists[site_idx];
-                for ridx in &edges[site_idx] {
-                    let river = &self.map.rivers[*ridx];
-                    let neighbor = river.other_index(site_idx);
-                    if mine_dists[neighbor] == usize::max_value() {
-                        mine_dists[neighbor] = site_dist + 1;
-                        que.push_back(neighbor);
-                    }
-                }
-            }
-        }
-        shortest_paths
+    /// Our punter id
+    pub fn punter(&self) -> PunterId {
+        self.punter
     }
 }
 
 // This structure contains the entire state of a punter
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Punter {
-    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
-    input: Input,
-
-    // Reverse id-to-idx mappings
-    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
-    site_index: SiteIndex,
-
-    // The edges represented as an incidence matrix:
-    // for every site, we keep a list of all its rivers
-    // The list of edges is sorted in increasing order of
-    // river.other_side(site)
-    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
-    edges: EdgeMatrix,
-    #[serde(serialize_with="bin_serializer", deserialize_with="bin_deserializer")]
-    shortest_paths: ShortestPathsMap,
+    // Our punter id
+    pub(crate) punter: PunterId,
+
+    // The map and everything that happened on it, kept by the same rules
+    // the server plays by
+    pub(crate) game: GameState,
 
     ai: PunterType,
-}
 
-fn bin_serializer<S, T>(field: T, serializer: S) -> Result<S::Ok, S::Error>
-    where S: Serializer,
-          T: Serialize
-{
-    serializer.serialize_bytes(&bincode::serialize(&field, bincode::Infinite).unwrap()[..])
-}
+    // Number of turn messages processed so far
+    #[serde(default)]
+    turn: usize,
+
+    // The last moves we've been told about, in order. The opponent models
+    // keep what we learn from older ones.
+    #[serde(default)]
+    history: Vec<ObservedMove>,
+
+    // One model per punter, indexed by punter id (ours included, but unused)
+    #[serde(default)]
+    opponents: Vec<OpponentModel>,
 
-fn bin_deserializer<'de, D, T>(deserializer: D) -> Result<T, D::Error>
-    where D: Deserializer<'de>,
-          T: DeserializeOwned
-{
-    let v: Vec<u8> = Deserialize::deserialize(deserializer)?;
-    Ok(bincode::deserialize(&v[..]).unwrap())
+    #[serde(default)]
+    pub(crate) config: SearchConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PunterType {
     Random,
     MCTS,
+    // Claim the river with the best immediate score gain
+    Greedy,
+    // Extend our network along the shortest path to the nearest unconnected mine
+    ConnectMines,
+    // Take the river the leading opponent would gain the most from
+    BlockLeader,
+    // Follow an approximate Steiner tree linking the mines
+    Steiner,
+}
+
+impl FromStr for PunterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PunterType, String> {
+        match s {
+            "random" => Ok(PunterType::Random),
+            "mcts" => Ok(PunterType::MCTS),
+            "greedy" => Ok(PunterType::Greedy),
+            "connect-mines" => Ok(PunterType::ConnectMines),
+            "block-leader" => Ok(PunterType::BlockLeader),
+            "steiner" => Ok(PunterType::Steiner),
+            _ => Err(format!("Unknown AI {}, expected one of random, mcts, greedy, \
+                              connect-mines, block-leader or steiner", s)),
+        }
+    }
 }
 
 impl Punter {
     pub fn new(input: Input, ai: PunterType) -> Punter {
-        // eprintln!("Mines {:#?}", input.map.mines);
-        let mut input = input; // Make a mutable copy of the input
-        let site_index = input.map.sites.iter().enumerate()
-            .map(|(idx, site)| (site.id, idx))
-            .collect::<HashMap<_, _>>();
-        input.index_rivers(&site_index);
-        let edges = input.compute_edges(&site_index);
-        let shortest_paths = input.compute_shortest_paths(&edges, &site_index);
+        trace!("setup", "Mines {:?}", input.map.mines);
+        let opponents = vec![OpponentModel::new(input.map.mines.len()); input.punters];
         Punter {
-            input: input,
-            site_index: site_index,
-            edges: edges,
-            shortest_paths: shortest_paths,
-            ai: ai,
+            punter: input.punter,
+            game: GameState::new(input.punters, input.map, input.settings),
+            ai,
+            turn: 0,
+            history: Vec::new(),
+            opponents,
+            config: SearchConfig::default(),
         }
     }
 
     pub fn id(&self) -> PunterId {
-        self.input.punter
+        self.punter
+    }
+
+    pub fn punters(&self) -> PunterId {
+        self.game.punters
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    /// Number of turn messages processed so far
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    /// Moves made in the game so far, passes included. Between turns, our
+    /// own last move only counts once the next message has told us about it.
+    pub fn moves_made(&self) -> usize {
+        self.game.moves()
+    }
+
+    /// Moves left in the game, our next one included. The game lasts one
+    /// move per river, however many of them get claimed.
+    pub fn moves_left(&self) -> usize {
+        self.game.total_moves() - self.game.moves()
+    }
+
+    /// The rules the game is played by, and where it stands
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    /// Whether we've had our last turn, so that the next message is the
+    /// stop
+    pub fn had_last_turn(&self) -> bool {
+        if self.turn == 0 {
+            self.num_rivers() <= self.punter
+        } else {
+            self.moves_left() <= self.game.punters
+        }
+    }
+
+    /// The most recent moves, oldest first
+    pub fn history(&self) -> &[ObservedMove] {
+        &self.history
+    }
+
+    pub fn opponent(&self, punter: PunterId) -> Option<&OpponentModel> {
+        self.opponents.get(punter)
+    }
+
+    pub fn num_sites(&self) -> usize {
+        self.game.map.sites.len()
+    }
+
+    pub fn num_rivers(&self) -> usize {
+        self.game.map.rivers.len()
+    }
+
+    /// Map coordinates of a site, if the map has them
+    pub fn site_coordinates(&self, site: SiteId) -> Option<(f64, f64)> {
+        let site = &self.game.map.sites[*self.game.site_index.get(&site)?];
+        site.x.and_then(|x| site.y.map(|y| (x, y)))
+    }
+
+    /// A field of the map we don't otherwise know about
+    pub fn map_field(&self, name: &str) -> Option<&Value> {
+        self.game.map.extra.get(name)
+    }
+
+    /// A fingerprint of the map's sites, rivers and mines, to tell maps apart
+    /// when the server doesn't name them. 64-bit FNV-1a, so it's the same
+    /// from one build to the next.
+    pub fn map_hash(&self) -> u64 {
+        let map = &self.game.map;
+        let numbers = iter::once(map.sites.len())
+            .chain(map.sites.iter().map(|site| site.id))
+            .chain(iter::once(map.rivers.len()))
+            .chain(map.rivers.iter().flat_map(|river| vec![river.source, river.target]))
+            .chain(map.mines.iter().cloned());
+        numbers.flat_map(|n| (n as u64).to_le_bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+    }
+
+    /// A field of a site we don't otherwise know about
+    pub fn site_field(&self, site: SiteId, name: &str) -> Option<&Value> {
+        self.game.map.sites[*self.game.site_index.get(&site)?].extra.get(name)
+    }
+
+    /// Site ids at both ends of a river
+    pub fn river_sites(&self, river: RiverIdx) -> (SiteId, SiteId) {
+        let river = &self.game.map.rivers[river];
+        (river.source, river.target)
+    }
+
+    pub fn river_owner(&self, river: RiverIdx) -> Option<PunterId> {
+        self.game.map.rivers[river].owner
     }
 
     /// Add the previous turns moves into the current state
-    pub fn process_turn(&mut self, moves: &Vec<protocol::Move>) {
+    pub fn process_turn(&mut self, moves: &[protocol::Move]) {
+        self.turn += 1;
+        // The server fills in a pass for everyone who hasn't moved yet
+        let first_turn = self.turn == 1;
+        let mut moves = moves.iter()
+            .filter(|m| match **m {
+                protocol::Move::pass (ref pass) => !first_turn || pass.punter < self.punter,
+                _ => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        // Moves come by punter id; put them in the order they were made
+        let (first, punters) = (self.game.next_punter(), self.game.punters);
+        moves.sort_by_key(|m| (m.punter() + punters - first) % punters);
+        self.add_moves(&moves);
+    }
+
+    /// Add the moves of the stop message. Like a turn message it lists the
+    /// last move of every punter, but only some of them are new: ours, and
+    /// those of the punters after us who still had a move left. The others
+    /// were in the last turn message already.
+    pub fn process_stop(&mut self, moves: &[protocol::Move]) {
+        let (first, punters) = (self.game.next_punter(), self.game.punters);
+        let new_moves = (0..self.moves_left().min(punters))
+            .filter_map(|i| moves.iter().find(|m| m.punter() == (first + i) % punters))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.add_moves(&new_moves);
+    }
+
+    // The server only passes on moves it accepted, so anything that doesn't
+    // fit means we've lost track of the game
+    fn add_moves(&mut self, moves: &[protocol::Move]) {
         for m in moves {
-            match m {
-                &protocol::Move::claim (
-                    protocol::Claim {punter, source, target}
-                ) => {
-                    self.add_move(punter, source, target);
-                }
-                &protocol::Move::option (
-                    protocol::Claim {punter, source, target}
-                ) => {
-                    self.add_move(punter, source, target);
-                }
-                &protocol::Move::splurge (
-                    protocol::Splurge {punter, ref route}
-                ) => {
-                    let mut source = route[0];
-                    for target in &route[1..] {
-                        self.add_move(punter, source, *target);
-                        source = *target;
-                    }
-                }
-                &protocol::Move::pass (_) => { }
-            }
+            let rivers = self.game.apply(m)
+                .unwrap_or_else(|e| panic!("Move {:?} doesn't fit the game: {}", m, e));
+            // A splurge's route hangs off its first river
+            let connected = rivers.first()
+                .is_some_and(|ridx| self.touches_network(m.punter(), *ridx, &rivers));
+            self.record_move(m.punter(), rivers, connected);
         }
     }
 
-    pub fn make_move(&self, begin_time: Instant, timeout: u8) -> protocol::Move {
-        let play = match self.ai {
-            PunterType::Random => self.move_random(),
-            PunterType::MCTS   => self.move_mcts(begin_time, timeout),
+    /// How long it takes to write out our state for the offline protocol,
+    /// with room to spare. It grows with the rivers, and with the sites once
+    /// for the map and once for every mine's distances.
+    pub fn state_reserve(&self) -> Duration {
+        let items = self.game.num_rivers() + self.game.num_sites() * (self.game.mines.len() + 1);
+        Duration::from_nanos(items as u64 * STATE_NS_PER_ITEM)
+    }
+
+    pub fn make_move(&self, begin_time: Instant, budget: Duration) -> protocol::Move {
+        self.choose_move(begin_time, budget, None).0
+    }
+
+    /// Like `make_move`, but also reports on the tree search behind the
+    /// move. The report is None unless a tree search ran.
+    pub fn make_move_diagnosed(&self, begin_time: Instant, budget: Duration,
+                               diagnostics: &Diagnostics) -> (protocol::Move, Option<SearchStats>) {
+        self.choose_move(begin_time, budget, Some(diagnostics))
+    }
+
+    fn choose_move(&self, begin_time: Instant, budget: Duration,
+                   diagnostics: Option<&Diagnostics>) -> (protocol::Move, Option<SearchStats>) {
+        let (play, stats) = match self.ai {
+            PunterType::Random       => (self.move_random(), None),
+            PunterType::MCTS         => self.move_mcts(begin_time, budget, diagnostics),
+            PunterType::Greedy       => (self.move_greedy(), None),
+            PunterType::ConnectMines => (self.move_connect_mines(), None),
+            PunterType::BlockLeader  => (self.move_block_leader(), None),
+            PunterType::Steiner      => (self.move_steiner(), None),
         };
 
-        protocol::Move::claim (protocol::Claim {
-            punter: play.punter,
-            source: play.source,
-            target: play.target,
-        })
+        let next_move = match play {
+            Some(play) => protocol::Move::claim (protocol::Claim {
+                punter: play.punter,
+                source: play.source,
+                target: play.target,
+            }),
+            // Nothing left to claim
+            None => protocol::Move::pass (protocol::Pass {
+                punter: self.id(),
+            }),
+        };
+        (next_move, stats)
     }
 
-    pub fn compute_scores(&self, rivers: &Vec<River>, scores: &mut Vec<u64>) {
-        let mut que: VecDeque<SiteIdx> = VecDeque::with_capacity(self.input.map.sites.len());
-        let mut visited = vec![false; self.input.map.sites.len()];
-        scores.resize(self.input.punters, 0);
-        for punter in 0..self.input.punters {
-            scores[punter] = 0;
-            for (mine_idx, mine) in self.input.map.mines.iter().enumerate() {
-                let mine_site_idx = self.site_index[mine];
-                que.push_back(mine_site_idx);
-                for v in &mut visited {
-                    *v = false;
-                }
-                visited[mine_site_idx] = true;
-                while let Some(site_idx) = que.pop_front() {
-                    let dist = self.shortest_paths[mine_idx][site_idx];
-                    assert!(dist != usize::max_value());
-                    let dist_u64 = dist as u64;
-                    scores[punter] += dist_u64*dist_u64;
-                    for ridx in &self.edges[site_idx] {
-                        let river = &rivers[*ridx];
-                        if river.owner.map_or(true, |o| o != punter) &&
-                           river.renter.map_or(true, |o| o != punter) {
-                            continue;
-                        }
-                        let neighbor = river.other_index(site_idx);
-                        if !visited[neighbor] {
-                            visited[neighbor] = true;
-                            que.push_back(neighbor);
-                        }
+    /// Scores of all punters for the rivers claimed so far
+    pub fn scores(&self) -> Vec<i64> {
+        self.game.scores()
+    }
+
+    /// Record the futures a punter bet on, for scoring. Only possible before
+    /// the first move and when futures are enabled.
+    pub fn set_futures(&mut self, punter: PunterId, futures: Vec<protocol::Future>) -> Result<(), String> {
+        self.game.set_futures(punter, futures)
+    }
+
+    /// Compare our own scores against the ones reported by the server and
+    /// return every punter we disagree about.
+    pub fn score_mismatches(&self, reported: &[protocol::Score]) -> Vec<ScoreMismatch> {
+        let scores = self.scores();
+        reported.iter()
+            .filter(|s| scores.get(s.punter).map(|&ours| ours as isize) != Some(s.score))
+            .map(|s| ScoreMismatch {
+                punter: s.punter,
+                computed: scores.get(s.punter).cloned(),
+                reported: s.score,
+            })
+            .collect()
+    }
+
+    /// Explain every punter's score: the sites reached from each mine and
+    /// what they are worth, plus futures and option usage when those are
+    /// enabled.
+    pub fn score_breakdown(&self) -> Vec<ScoreBreakdown> {
+        let rivers = &self.game.map.rivers;
+        let settings = &self.game.settings;
+        (0..self.game.punters).map(|punter| {
+            let reachable = self.game.map.mines.iter()
+                .map(|mine| self.reachable_sites(rivers, punter, self.game.site_index[mine]))
+                .collect::<Vec<_>>();
+            let mines = self.game.map.mines.iter().enumerate()
+                .map(|(mine_idx, mine)| {
+                    let sites = &reachable[mine_idx];
+                    MineScore {
+                        mine: *mine,
+                        score: sites.iter()
+                            .filter_map(|site_idx| self.distance(mine_idx, *site_idx))
+                            .map(|dist| dist * dist)
+                            .sum(),
+                        reachable: sites.iter()
+                            .map(|site_idx| self.game.map.sites[*site_idx].id)
+                            .collect(),
                     }
-                }
+                })
+                .collect::<Vec<_>>();
+            let futures = if settings.futures {
+                Some(self.game.futures(punter).iter().filter_map(|future| {
+                    let mine_idx = self.game.map.mines.iter().position(|m| *m == future.source)?;
+                    let target_idx = *self.game.site_index.get(&future.target)?;
+                    // Targets the mine can't reach are worth nothing
+                    let dist = self.distance(mine_idx, target_idx).unwrap_or(0);
+                    let fulfilled = reachable[mine_idx].contains(&target_idx);
+                    Some(FutureScore {
+                        source: future.source,
+                        target: future.target,
+                        fulfilled,
+                        score: game::future_score(dist, fulfilled),
+                    })
+                }).collect::<Vec<_>>())
+            } else {
+                None
+            };
+            let options = if settings.options {
+                Some(OptionUsage {
+                    used: rivers.iter().filter(|r| r.renter == Some(punter)).count(),
+                    available: self.game.map.mines.len(),
+                })
+            } else {
+                None
+            };
+            let total = mines.iter().map(|m| m.score as i64).sum::<i64>() +
+                futures.as_ref().map_or(0, |f| f.iter().map(|f| f.score).sum::<i64>());
+            ScoreBreakdown {
+                punter,
+                total,
+                mines,
+                futures,
+                options,
+            }
+        }).collect()
+    }
+
+    pub fn compute_scores(&self, rivers: &[River], scores: &mut Vec<u64>) {
+        let mut que: VecDeque<SiteIdx> = VecDeque::with_capacity(self.game.map.sites.len());
+        let mut visited = vec![false; self.game.map.sites.len()];
+        scores.resize(self.game.punters, 0);
+        for (punter, score) in scores.iter_mut().enumerate() {
+            *score = 0;
+            for (mine_idx, mine) in self.game.map.mines.iter().enumerate() {
+                *score += game::mine_score(rivers, &self.game.edges, punter, self.game.site_index[mine],
+                                           &self.game.distances[mine_idx], &mut visited, &mut que);
             }
         }
     }
@@ -298,57 +506,440 @@ impl Punter {
     ////////////////////////////////////////////////////////////////////////////
     // Implemented AIs
     ////////////////////////////////////////////////////////////////////////////
-    fn move_random(&self) -> Play {
-        let river_iter = self.input.map.rivers.iter();
+    fn move_random(&self) -> Option<Play> {
+        let river_iter = self.game.map.rivers.iter();
         let mut rng = thread_rng();
         let choices = &river_iter.filter(|x| x.owner.is_none()).collect::<Vec<&River>>();
-        let choice = rng.choose(choices).unwrap();
-        Play {
-            punter: self.id(),
-            source: choice.source,
-            target: choice.target,
-        }
+        rng.choose(choices).map(|choice| Play::new(choice, self.id()))
     }
 
-    fn move_mcts(&self, begin_time: Instant, timeout: u8) -> Play {
-        let mut mcts = MCTS::new(self, 1.);
+    fn move_mcts(&self, begin_time: Instant, budget: Duration,
+                 diagnostics: Option<&Diagnostics>) -> (Option<Play>, Option<SearchStats>) {
+        let free_rivers = self.game.map.rivers.iter()
+            .filter(|river| river.owner.is_none())
+            .count();
+        if free_rivers > 0 && free_rivers <= self.config.endgame_threshold {
+            if let Some(ridx) = endgame::solve(self, begin_time, budget) {
+                return (Some(Play::new(self.river(ridx), self.id())), None);
+            }
+        }
+        let mut mcts = Mcts::new(self, 1.);
+        let mut game = InternalGameState::new(self);
         let mut iterations = 0;
-        let mut game = InternalGameState::new(&self);
-        let duration = Duration::from_millis((timeout as u64 * 1000) - 100);
-        while begin_time.elapsed() < duration {
+        // Writing out the tree comes out of the same budget as the search
+        let dump_ns = match diagnostics.and_then(|d| d.tree) {
+            Some(TreeFormat::Json) => JSON_DUMP_NS_PER_NODE,
+            Some(TreeFormat::Dot) => DOT_DUMP_NS_PER_NODE,
+            None => 0,
+        };
+        while begin_time.elapsed() + Duration::from_nanos(dump_ns * mcts.nodes as u64) < budget {
             game.reset_game();
             mcts.step(&mut game);
             iterations += 1;
-            // eprintln!("MCTS: {:#?}", mcts.root);
         }
-        // eprintln!("Ran {} iterations", iterations);
-        mcts.best_move()
+        let stats = diagnostics.map(|d| mcts.stats(d, iterations, begin_time.elapsed()));
+        if iterations < MIN_MCTS_ITERATIONS {
+            return (self.move_greedy(), stats);
+        }
+        (mcts.best_move().or_else(|| self.move_greedy()), stats)
+    }
+
+    fn move_greedy(&self) -> Option<Play> {
+        let gains = self.claim_gains(self.id());
+        let best = self.best_claim(&gains);
+        match best {
+            Some(ridx) if gains[ridx] > 0 => Some(Play::new(self.river(ridx), self.id())),
+            // Nothing scores right away, so start building towards a mine
+            _ => self.move_connect_mines(),
+        }
+    }
+
+    fn move_connect_mines(&self) -> Option<Play> {
+        let in_tree = match planner::initial_tree(self, self.id()) {
+            Some(in_tree) => in_tree,
+            None => return self.first_free_river(),
+        };
+        let mines = planner::unconnected_mines(self, &in_tree);
+        let path = planner::shortest_path_to(self, &in_tree, &mines)
+            .map_or(vec![], |(_, path)| path);
+        // Claim the free river on the path closest to our network
+        match path.iter().find(|ridx| self.river(**ridx).owner.is_none()) {
+            Some(ridx) => Some(Play::new(self.river(*ridx), self.id())),
+            None => self.move_greedy_fallback(),
+        }
+    }
+
+    fn move_steiner(&self) -> Option<Play> {
+        let plan = Plan::new(self);
+        if plan.rivers.is_empty() {
+            return self.move_greedy();
+        }
+        // Of all the rivers in the plan, take the one that scores the most
+        // now; earlier ones in the plan win ties
+        let gains = self.claim_gains(self.id());
+        let best = plan.rivers.iter().enumerate()
+            .max_by_key(|&(order, ridx)| (gains[*ridx], Reverse(order)))
+            .map(|(_, ridx)| *ridx)
+            .unwrap();
+        Some(Play::new(self.river(best), self.id()))
+    }
+
+    fn move_block_leader(&self) -> Option<Play> {
+        let scores = self.scores();
+        let leader = (0..self.game.punters)
+            .filter(|p| *p != self.id())
+            .max_by_key(|p| (scores[*p], Reverse(*p)));
+        let leader = match leader {
+            Some(leader) => leader,
+            None => return self.move_greedy(),
+        };
+        let gains = self.claim_gains(leader);
+        let best = self.best_claim(&gains);
+        match best {
+            Some(ridx) if gains[ridx] > 0 => Some(Play::new(self.river(ridx), self.id())),
+            // Nothing the leader can score right away, so cut it off from
+            // what it's heading for instead
+            _ => match Criticality::new(self, leader).most_critical() {
+                Some(ridx) => Some(Play::new(self.river(ridx), self.id())),
+                None => self.move_greedy(),
+            },
+        }
+    }
+
+    // Best immediate gain, or the first free river if nothing scores. Used
+    // when there's no mine left to connect to.
+    fn move_greedy_fallback(&self) -> Option<Play> {
+        let gains = self.claim_gains(self.id());
+        let best = self.best_claim(&gains);
+        best.map(|ridx| Play::new(self.river(ridx), self.id()))
+    }
+
+    // The free river with the highest gain, lowest index first on ties
+    pub(crate) fn best_claim(&self, gains: &[u64]) -> Option<RiverIdx> {
+        (0..gains.len())
+            .filter(|ridx| self.game.map.rivers[*ridx].owner.is_none())
+            .max_by_key(|ridx| (gains[*ridx], Reverse(*ridx)))
+    }
+
+    fn first_free_river(&self) -> Option<Play> {
+        self.game.map.rivers.iter()
+            .find(|river| river.owner.is_none())
+            .map(|river| Play::new(river, self.id()))
     }
 
     ////////////////////////////////////////////////////////////////////////////
     // Utilities
     ////////////////////////////////////////////////////////////////////////////
-    fn find_river(&self, source: SiteId, target: SiteId) -> Option<RiverIdx> {
-        let source_idx = self.site_index[&source];
-        let target_idx = self.site_index[&target];
-        self.edges[source_idx]
-            .binary_search_by_key(&target_idx, |river| self.input.river_other_index(*river, source_idx))
-            .map(|river| self.edges[source_idx][river])
-            .ok() // Result -> Option transform
+    pub(crate) fn site_has_river(&self, rivers: &[River], site: SiteIdx, punter: PunterId) -> bool {
+        self.game.edges[site].iter().any(|r| {
+            rivers[*r].is_held_by(punter)
+        })
+    }
+
+    // Label every site with the connected component it belongs to in the
+    // punter's network; sites the punter hasn't reached are singletons
+    pub(crate) fn components(&self, rivers: &[River], punter: PunterId) -> Vec<usize> {
+        let mut component = vec![usize::MAX; self.game.map.sites.len()];
+        for start in 0..component.len() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            for site in self.reachable_sites(rivers, punter, start) {
+                component[site] = start;
+            }
+        }
+        component
+    }
+
+    /// How much the punter's score would go up by claiming each river, as a
+    /// vector indexed by river. Claiming a river only scores when it joins
+    /// two of the punter's components: every mine on one side gains the
+    /// squared distances of all sites on the other.
+    pub(crate) fn claim_gains(&self, punter: PunterId) -> Vec<u64> {
+        let rivers = &self.game.map.rivers;
+        let components = self.components(rivers, punter);
+        let sites = self.game.map.sites.len();
+
+        // mine_values[m][c]: what component c is worth to mine m
+        let mine_values = self.game.distances.iter().map(|dists| {
+            let mut values = vec![0u64; sites];
+            for (site, dist) in dists.iter().enumerate() {
+                if *dist != usize::MAX {
+                    values[components[site]] += (*dist as u64) * (*dist as u64);
+                }
+            }
+            values
+        }).collect::<Vec<_>>();
+        let mine_components = self.game.map.mines.iter()
+            .map(|mine| components[self.game.site_index[mine]])
+            .collect::<Vec<_>>();
+        let gain = |from: usize, to: usize| -> u64 {
+            mine_components.iter().enumerate()
+                .filter(|&(_, c)| *c == from)
+                .map(|(m, _)| mine_values[m][to])
+                .sum()
+        };
+
+        rivers.iter().map(|river| {
+            let a = components[river.source_idx];
+            let b = components[river.target_idx];
+            if a == b {
+                return 0;
+            }
+            gain(a, b) + gain(b, a)
+        }).collect()
+    }
+
+    // All sites connected to `start` through rivers owned or rented by
+    // `punter`, including `start` itself
+    pub(crate) fn reachable_sites(&self, rivers: &[River], punter: PunterId,
+                       start: SiteIdx) -> Vec<SiteIdx> {
+        // A set rather than a flag per site: networks are small next to the
+        // biggest maps, and this runs for every claim
+        let mut visited = HashSet::new();
+        let mut sites = vec![start];
+        visited.insert(start);
+        let mut next = 0;
+        while next < sites.len() {
+            let site_idx = sites[next];
+            next += 1;
+            for ridx in &self.game.edges[site_idx] {
+                let river = &rivers[*ridx];
+                if !river.is_held_by(punter) {
+                    continue;
+                }
+                let neighbor = river.other_index(site_idx);
+                if visited.insert(neighbor) {
+                    sites.push(neighbor);
+                }
+            }
+        }
+        sites
+    }
+
+    pub(crate) fn river(&self, id: RiverIdx) -> &River {
+        &self.game.map.rivers[id]
+    }
+
+    pub(crate) fn is_mine(&self, site: SiteIdx) -> bool {
+        self.game.distances.iter().any(|dists| dists[site] == 0)
+    }
+
+    // Length of the shortest route from the mine to the site, or None if
+    // they are on different islands of the map
+    pub(crate) fn distance(&self, mine_idx: usize, site: SiteIdx) -> Option<u64> {
+        match self.game.distances[mine_idx][site] {
+            usize::MAX => None,
+            dist => Some(dist as u64),
+        }
+    }
+
+    // Whether the river starts at a mine or at a site already connected to
+    // one of the punter's rivers, other than those the move just took
+    fn touches_network(&self, punter: PunterId, ridx: RiverIdx, taken: &[RiverIdx]) -> bool {
+        let rivers = &self.game.map.rivers;
+        let river = &rivers[ridx];
+        [river.source_idx, river.target_idx].iter().any(|site| {
+            self.is_mine(*site) || self.game.edges[*site].iter().any(|r| {
+                rivers[*r].is_held_by(punter) && !taken.contains(r)
+            })
+        })
+    }
+
+    fn record_move(&mut self, punter: PunterId, rivers: Vec<RiverIdx>, connected: bool) {
+        let mines = match rivers.first() {
+            Some(ridx) => {
+                let start = self.game.map.rivers[*ridx].source_idx;
+                let sites = self.reachable_sites(&self.game.map.rivers, punter, start);
+                (0..self.game.map.mines.len())
+                    .filter(|m| sites.iter().any(|site| self.game.distances[*m][*site] == 0))
+                    .collect()
+            }
+            None => vec![],
+        };
+        if let Some(model) = self.opponents.get_mut(punter) {
+            model.observe(self.turn, !rivers.is_empty(), connected, &mines);
+        }
+        if self.history.len() == HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(ObservedMove {
+            turn: self.turn,
+            punter,
+            rivers,
+        });
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScoreMismatch {
+    pub punter: PunterId,
+    // None if the server reported a punter we don't know about
+    pub computed: Option<i64>,
+    pub reported: isize,
+}
+
+/// Where a punter's points come from, as returned by
+/// `Punter::score_breakdown`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScoreBreakdown {
+    pub punter: PunterId,
+    pub total: i64,
+    pub mines: Vec<MineScore>,
+    // Only present if the game has futures/options enabled
+    pub futures: Option<Vec<FutureScore>>,
+    pub options: Option<OptionUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MineScore {
+    pub mine: SiteId,
+    pub score: u64,
+    // Every site connected to the mine, the mine itself included
+    pub reachable: Vec<SiteId>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FutureScore {
+    pub source: SiteId,
+    pub target: SiteId,
+    pub fulfilled: bool,
+    pub score: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OptionUsage {
+    pub used: usize,
+    pub available: usize,
+}
+
+/// Search settings. They live in the punter state, so an offline punter
+/// keeps the settings it was set up with for the whole game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SearchConfig {
+    // Expand rivers on the planner's Steiner tree before any others
+    pub planner_prior: bool,
+
+    // Solve the game exactly instead of searching once at most this many
+    // rivers are left
+    pub endgame_threshold: usize,
+
+    // Share search nodes between claim orders that reach the same position.
+    // Off by default; self-play on the bundled maps (see the benches) hasn't
+    // shown a consistent gain yet.
+    pub transpositions: bool,
+
+    // RAVE schedule: how many visits a child needs before its own mean
+    // counts as much as its all-moves-as-first mean. 0 disables RAVE.
+    pub rave_equivalence: f64,
+
+    // Most search nodes to keep at once. Past this, the least visited half
+    // of the tree is dropped. 0 for no limit.
+    pub max_nodes: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            planner_prior: true,
+            endgame_threshold: 10,
+            transpositions: false,
+            rave_equivalence: 300.,
+            max_nodes: 100_000,
+        }
+    }
+}
+
+/// What to report about the search behind each move, see
+/// `Punter::make_move_diagnosed`
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostics {
+    // How many of the most visited root children to list
+    pub top_k: usize,
+    // Also export the whole search tree in this format
+    pub tree: Option<TreeFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeFormat {
+    Json,
+    Dot,
+}
+
+impl TreeFormat {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            TreeFormat::Json => "json",
+            TreeFormat::Dot => "dot",
+        }
     }
+}
+
+impl FromStr for TreeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TreeFormat, String> {
+        match s {
+            "json" => Ok(TreeFormat::Json),
+            "dot" => Ok(TreeFormat::Dot),
+            _ => Err(format!("Unknown tree format {}, expected json or dot", s)),
+        }
+    }
+}
+
+/// One move's tree search, as returned by `Punter::make_move_diagnosed`
+#[derive(Serialize, Debug)]
+pub struct SearchStats {
+    pub iterations: usize,
+    pub elapsed_ms: f64,
+    // Longest path from the root
+    pub depth: usize,
+    pub nodes: usize,
+    // Most nodes alive at once, which is more than `nodes` if the tree was
+    // pruned
+    pub peak_nodes: usize,
+    // Mean claims per iteration, down the tree and on to the end of the game
+    pub playout_claims: f64,
+    // Most visited first
+    pub children: Vec<ChildStats>,
+
+    // The whole tree, if asked for
+    #[serde(skip)]
+    pub tree: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChildStats {
+    pub source: SiteId,
+    pub target: SiteId,
+    pub visits: u64,
+    pub mean: f64,
+}
 
-    #[allow(dead_code)]
-    fn river(&self, id: RiverIdx) -> &River {
-        &self.input.map.rivers[id]
+/// How long we let ourselves think about each move. Starts at the server's
+/// timeout less a safety margin; every timeout notice from the server doubles
+/// the margin, up to half of the timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeBudget {
+    timeout: Duration,
+    margin: Duration,
+}
+
+impl TimeBudget {
+    pub fn new(timeout: u8) -> TimeBudget {
+        TimeBudget {
+            timeout: Duration::from_secs(timeout as u64),
+            margin: Duration::from_millis(TIMEOUT_MARGIN_MS),
+        }
     }
 
-    fn river_mut(&mut self, id: RiverIdx) -> &mut River {
-        &mut self.input.map.rivers[id]
+    pub fn duration(&self) -> Duration {
+        self.timeout.checked_sub(self.margin).unwrap_or_default()
     }
 
-    fn add_move(&mut self, punter: PunterId, source: SiteId, target: SiteId) {
-        let id = self.find_river(source, target).unwrap();
-        self.river_mut(id).add_owner(punter);
+    pub fn timed_out(&mut self) {
+        self.margin = (self.margin * 2).min(self.timeout / 2);
     }
 }
 
@@ -362,7 +953,7 @@ struct Play {
 impl Play {
     fn new(river: &River, punter: PunterId) -> Play {
         Play {
-            punter: punter,
+            punter,
             source: river.source,
             target: river.target,
         }
@@ -378,51 +969,148 @@ enum GameStatus {
     Finished,
 }
 
-struct InternalGameState<'a> {
+// A set of rivers that can be listed, and sampled, as a slice. Removal
+// moves the last river into the gap, so the order means nothing.
+#[derive(Debug, Clone)]
+struct RiverSet {
+    rivers: Vec<RiverIdx>,
+    // Indexed by river, usize::MAX if it's not in the set
+    position: Vec<usize>,
+}
+
+impl RiverSet {
+    fn new(num_rivers: usize) -> RiverSet {
+        RiverSet {
+            rivers: vec![],
+            position: vec![usize::MAX; num_rivers],
+        }
+    }
+
+    fn insert(&mut self, ridx: RiverIdx) {
+        if self.position[ridx] == usize::MAX {
+            self.position[ridx] = self.rivers.len();
+            self.rivers.push(ridx);
+        }
+    }
+
+    fn remove(&mut self, ridx: RiverIdx) {
+        let pos = self.position[ridx];
+        if pos == usize::MAX {
+            return;
+        }
+        self.rivers.swap_remove(pos);
+        if let Some(&moved) = self.rivers.get(pos) {
+            self.position[moved] = pos;
+        }
+        self.position[ridx] = usize::MAX;
+    }
+
+    fn clear(&mut self) {
+        for ridx in self.rivers.drain(..) {
+            self.position[ridx] = usize::MAX;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rivers.is_empty()
+    }
+
+    fn as_slice(&self) -> &[RiverIdx] {
+        &self.rivers
+    }
+}
+
+pub(crate) struct InternalGameState<'a> {
     // Constant immutable state
     state: &'a Punter,
 
     // Per-game state
     status: GameStatus,
     current_punter: PunterId,
+    // Moves until the end of the game, passes included
+    moves_left: usize,
     rivers: Vec<River>,
-    available_rivers: HashSet<RiverIdx>,
+    // Rivers the search considers: a subset of the free rivers near where
+    // the game is being played, to keep the tree narrow
+    available_rivers: RiverSet,
+    // Every free river, which is what rollouts may claim once nothing is
+    // available
+    free_rivers: RiverSet,
+    // Indexed by punter: the sites at both ends of every river the punter
+    // holds, in the order they were claimed, repeats and all
+    networks: Vec<Vec<SiteIdx>>,
     scores: Vec<u64>,
+
+    // Zobrist keys: one per river and claimant, then one per punter to move.
+    // Only the transposition table needs them; without it there are none and
+    // the hash stays 0.
+    zobrist: Vec<u64>,
+    // Hash of the claims made since the real game and whose turn it is
+    hash: u64,
 }
 
 impl<'a> InternalGameState<'a> {
-    fn new(state: &'a Punter) -> InternalGameState {
-        let available_rivers_len = (0..state.input.map.rivers.len())
-                .filter(|x| state.input.map.rivers[*x].owner.is_none())
-                .count();
+    pub(crate) fn new(state: &'a Punter) -> InternalGameState<'a> {
+        let mut rng = thread_rng();
+        let keys = if state.config.transpositions {
+            (state.game.map.rivers.len() + 1) * state.game.punters
+        } else {
+            0
+        };
+        let zobrist = (0..keys).map(|_| rng.gen::<u64>()).collect();
         InternalGameState {
-            state: state,
+            state,
             status: GameStatus::NotStarted,
             current_punter: state.id(),
-            rivers: Vec::with_capacity(state.input.map.rivers.len()),
-            available_rivers: HashSet::with_capacity(available_rivers_len),
-            scores: Vec::with_capacity(state.input.punters),
+            moves_left: state.moves_left(),
+            rivers: Vec::with_capacity(state.game.map.rivers.len()),
+            available_rivers: RiverSet::new(state.game.map.rivers.len()),
+            free_rivers: RiverSet::new(state.game.map.rivers.len()),
+            networks: vec![vec![]; state.game.punters],
+            scores: Vec::with_capacity(state.game.punters),
+            zobrist,
+            hash: 0,
         }
     }
 
     fn reset_game(&mut self) {
-        let input_rivers = &self.state.input.map.rivers;
+        self.reset(AVAILABLE_RADIUS);
+    }
+
+    // Start over from the real game, with the free rivers within `radius`
+    // of the mines and claimed rivers available, or all of them for None
+    pub(crate) fn reset(&mut self, radius: Option<usize>) {
+        let input_rivers = &self.state.game.map.rivers;
         self.status = GameStatus::Playing;
         self.current_punter = self.state.id();
+        self.moves_left = self.state.moves_left();
+        self.hash = self.turn_key(self.current_punter);
         {
             self.rivers.clear();
             self.rivers.extend_from_slice(input_rivers);
         }
+        self.free_rivers.clear();
+        for network in &mut self.networks {
+            network.clear();
+        }
+        for (ridx, river) in input_rivers.iter().enumerate() {
+            for punter in river.owner.iter().chain(river.renter.iter()) {
+                self.networks[*punter].extend_from_slice(&[river.source_idx, river.target_idx]);
+            }
+            if river.owner.is_none() {
+                self.free_rivers.insert(ridx);
+            }
+        }
         self.available_rivers.clear();
-        match AVAILABLE_RADIUS {
+        match radius {
             None => {
-                let available_rivers = (0..input_rivers.len())
-                    .filter(|x| input_rivers[*x].owner.is_none());
-                self.available_rivers.extend(available_rivers);
+                for ridx in (0..input_rivers.len()).filter(|x| input_rivers[*x].owner.is_none()) {
+                    self.available_rivers.insert(ridx);
+                }
             }
             Some(radius) => {
-                let mut que: VecDeque<SiteIdx> = VecDeque::with_capacity(self.state.input.map.sites.len());
-                let mut visited = vec![false; self.state.input.map.sites.len()];
+                let mut que: VecDeque<SiteIdx> = VecDeque::with_capacity(self.state.game.map.sites.len());
+                let mut visited = vec![false; self.state.game.map.sites.len()];
                 let owned_rivers = (0..input_rivers.len())
                     .filter(|x| input_rivers[*x].owner.is_some());
                 for ridx in owned_rivers {
@@ -432,8 +1120,8 @@ impl<'a> InternalGameState<'a> {
                     visited[river.source_idx] = true;
                     visited[river.target_idx] = true;
                 }
-                for site in &self.state.input.map.mines {
-                    let site_idx = self.state.site_index[&site];
+                for site in &self.state.game.map.mines {
+                    let site_idx = self.state.game.site_index[site];
                     que.push_back(site_idx);
                     visited[site_idx] = true;
                 }
@@ -442,7 +1130,7 @@ impl<'a> InternalGameState<'a> {
                 while let Some(site_idx) = que.pop_front() {
                     count -= 1;
 
-                    for ridx in &self.state.edges[site_idx] {
+                    for ridx in &self.state.game.edges[site_idx] {
                         let river = &self.rivers[*ridx];
                         if river.owner.is_some() {
                             continue;
@@ -464,22 +1152,105 @@ impl<'a> InternalGameState<'a> {
                 }
             }        
         }
+        if self.moves_left == 0 || self.free_rivers.is_empty() {
+            self.status = GameStatus::Finished;
+        }
+    }
+}
+
+impl<'a> InternalGameState<'a> {
+    fn pass(&mut self) {
+        let next = (self.current_punter + 1) % self.state.game.punters;
+        self.hash ^= self.turn_key(self.current_punter) ^ self.turn_key(next);
+        self.current_punter = next;
+        self.end_move();
+    }
+
+    // Count down to the end of the game, which comes after one move per
+    // river or once there is nothing left to claim anywhere on the map
+    fn end_move(&mut self) {
+        self.moves_left = self.moves_left.saturating_sub(1);
+        if self.moves_left == 0 || self.free_rivers.is_empty() {
+            self.status = GameStatus::Finished;
+        }
+    }
+
+    fn river_key(&self, ridx: RiverIdx, punter: PunterId) -> u64 {
+        self.zobrist.get(ridx * self.state.game.punters + punter).cloned().unwrap_or(0)
+    }
+
+    fn turn_key(&self, punter: PunterId) -> u64 {
+        let rivers = self.state.game.map.rivers.len();
+        self.zobrist.get(rivers * self.state.game.punters + punter).cloned().unwrap_or(0)
+    }
+
+    fn position_hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub(crate) fn river_owner(&self, ridx: RiverIdx) -> Option<PunterId> {
+        self.rivers[ridx].owner
+    }
+
+    // Take back a claim of a free river by `punter`. Only exact when every
+    // free river was available to begin with, so that the claim didn't make
+    // any new ones available.
+    pub(crate) fn undo_move(&mut self, ridx: RiverIdx, punter: PunterId) {
+        self.hash ^= self.river_key(ridx, punter)
+            ^ self.turn_key(self.current_punter) ^ self.turn_key(punter);
+        self.rivers[ridx].owner = None;
+        self.available_rivers.insert(ridx);
+        self.free_rivers.insert(ridx);
+        let network = &mut self.networks[punter];
+        network.truncate(network.len() - 2);
+        self.current_punter = punter;
+        self.moves_left += 1;
+        self.status = GameStatus::Playing;
+    }
+
+    // A free river that extends the punter's network, or starts at the
+    // given mine. Looks at a few sites of the network picked at random
+    // rather than the whole frontier, which rollouts can't afford on every
+    // move.
+    fn frontier_river(&self, punter: PunterId, mine: Option<SiteIdx>,
+                      rng: &mut ThreadRng) -> Option<RiverIdx> {
+        let network = &self.networks[punter];
+        let sites = network.len() + mine.iter().len();
+        if sites == 0 {
+            return None;
+        }
+        for _ in 0..FRONTIER_TRIES {
+            let site = network.get(rng.gen_range(0, sites)).cloned().or(mine)?;
+            let mut free = self.state.game.edges[site].iter()
+                .filter(|ridx| self.rivers[**ridx].owner.is_none());
+            let count = free.clone().count();
+            if count > 0 {
+                return free.nth(rng.gen_range(0, count)).cloned();
+            }
+        }
+        None
     }
 }
 
 impl<'a> Game<RiverIdx> for InternalGameState<'a> {
-    fn available_actions (&self) -> &HashSet<RiverIdx> {
+    fn available_actions (&self) -> &[RiverIdx] {
         assert!(self.status != GameStatus::NotStarted);
-        &self.available_rivers
+        if self.status == GameStatus::Finished {
+            &[]
+        } else {
+            self.available_rivers.as_slice()
+        }
     }
 
     fn make_move(&mut self, ridx: RiverIdx) {
         assert!(self.status == GameStatus::Playing);
         self.rivers[ridx].add_owner(self.current_punter);
-        self.available_rivers.remove(&ridx);
+        self.available_rivers.remove(ridx);
+        self.free_rivers.remove(ridx);
         let river = &self.rivers[ridx];
+        self.networks[self.current_punter].extend_from_slice(&[river.source_idx, river.target_idx]);
         for site_idx in [river.source_idx, river.target_idx].iter() {
-            for neighbor_ridx in &self.state.edges[*site_idx] {
+            for neighbor_ridx in &self.state.game.edges[*site_idx] {
                 let neighbor = &self.rivers[*neighbor_ridx];
                 if neighbor.owner.is_some() {
                     continue;
@@ -487,10 +1258,11 @@ impl<'a> Game<RiverIdx> for InternalGameState<'a> {
                 self.available_rivers.insert(*neighbor_ridx);
             }
         }
-        self.current_punter = (self.current_punter + 1) % self.state.input.punters;
-        if self.available_rivers.is_empty() {
-            self.status = GameStatus::Finished;
-        }
+        let next = (self.current_punter + 1) % self.state.game.punters;
+        self.hash ^= self.river_key(ridx, self.current_punter)
+            ^ self.turn_key(self.current_punter) ^ self.turn_key(next);
+        self.current_punter = next;
+        self.end_move();
     }
 
     fn score(&mut self) -> f64 {
@@ -505,6 +1277,48 @@ impl<'a> Game<RiverIdx> for InternalGameState<'a> {
         }
         total / (self.scores.len() - 1) as f64
     }
+
+    fn current_player(&self) -> PunterId {
+        self.current_punter
+    }
+
+    /// Opponents we have a model for pass and extend their networks as often
+    /// as they did in the real game; everyone else plays uniformly at random.
+    fn rollout_move(&mut self, rng: &mut ThreadRng) -> Option<RiverIdx> {
+        for _ in 0..self.state.game.punters {
+            if self.status == GameStatus::Finished {
+                return None;
+            }
+            let punter = self.current_punter;
+            if punter == self.state.id() {
+                break;
+            }
+            let model = match self.state.opponents.get(punter) {
+                Some(model) if model.is_informed() => model,
+                _ => break,
+            };
+            if rng.gen::<f64>() < model.pass_rate() {
+                self.pass();
+                continue;
+            }
+            if rng.gen::<f64>() < model.greediness() {
+                let mine = model.favourite_mine()
+                    .map(|m| self.state.game.site_index[&self.state.game.map.mines[m]]);
+                if let Some(ridx) = self.frontier_river(punter, mine, rng) {
+                    return Some(ridx);
+                }
+            }
+            break;
+        }
+        if self.status == GameStatus::Finished {
+            return None;
+        }
+        if self.available_rivers.is_empty() {
+            // Nothing left near the action, but the game goes on elsewhere
+            return rng.choose(self.free_rivers.as_slice()).cloned();
+        }
+        rng.choose(self.available_rivers.as_slice()).cloned()
+    }
 }
 
 
@@ -515,88 +1329,116 @@ enum NodeStatus {
 
 #[derive(Debug)]
 struct MCTSNode<A> {
-    play: Option<A>,
-    children: Vec<Rc<RefCell<MCTSNode<A>>>>,
-    parent: Option<Weak<RefCell<MCTSNode<A>>>>,
+    // Moves tried from this position and the positions they lead to. With
+    // transpositions, several nodes can share a child.
+    children: Vec<(A, Rc<RefCell<MCTSNode<A>>>)>,
     status: NodeStatus,
     score: f64,
     count: f64,
+    // All-moves-as-first statistics: total score and count of the playouts
+    // through this node in which the punter to move here made each move
+    // at any later point
+    amaf: HashMap<A, (f64, f64)>,
 }
 
 pub trait GameAction: Debug+Clone+Copy+Eq+Hash {}
 
-trait Game<A: GameAction> {
-    fn available_actions(&self) -> &HashSet<A>;
+pub(crate) trait Game<A: GameAction> {
+    fn available_actions(&self) -> &[A];
 
     fn make_move(&mut self, action: A);
 
     fn score(&mut self) -> f64;
+
+    fn current_player(&self) -> PunterId;
+
+    /// Pick the next move of a simulation, or None if the game is over.
+    /// Uniformly random unless the game knows better.
+    fn rollout_move(&mut self, rng: &mut ThreadRng) -> Option<A> {
+        rng.choose(self.available_actions()).cloned()
+    }
 }
 
-impl<'a, A: GameAction> MCTSNode<A> {
-    fn new(play: Option<A>) -> MCTSNode<A> {
+impl<A: GameAction> MCTSNode<A> {
+    fn new() -> MCTSNode<A> {
         MCTSNode::<A> {
-            play: play,
             children: Vec::new(),
-            parent: None,
             status: NodeStatus::Expandable,
             score: 0.,
             count: 0.,
+            amaf: HashMap::new(),
         }
     }
 
-    fn select_uct(&self, c: f64) -> Option<Rc<RefCell<MCTSNode<A>>>> {
+    /// UCT1 over the children, with each child's mean score blended with
+    /// its AMAF mean. The AMAF weight starts at 1 and drops off as the
+    /// child gets visits; `rave` is the visit count at which both means
+    /// weigh about the same. Zero turns RAVE off.
+    fn select_uct(&self, c: f64, rave: f64) -> Option<(A, Rc<RefCell<MCTSNode<A>>>)> {
         // We must be fully expanded, select a child based on UCT1
-        if self.children.len() == 0 {
+        if self.children.is_empty() {
             return None;
         }
-        let mut best_value = NEG_INFINITY;
+        let mut best_value = f64::NEG_INFINITY;
         let mut best_child = &self.children[0];
-        for child_ref in &self.children {
-            let child = child_ref.borrow();
-            let value = child.score / child.count + c*(2.*self.count.ln()/child.count).sqrt();
+        for child in &self.children {
+            let child_ref = child.1.borrow();
+            let mut mean = child_ref.score / child_ref.count;
+            if rave > 0. {
+                if let Some(&(amaf_score, amaf_count)) = self.amaf.get(&child.0) {
+                    let beta = (rave / (3. * child_ref.count + rave)).sqrt();
+                    mean = (1. - beta) * mean + beta * amaf_score / amaf_count;
+                }
+            }
+            let value = mean + c*(2.*self.count.ln()/child_ref.count).sqrt();
             if value > best_value {
                 best_value = value;
-                best_child = child_ref;
+                best_child = child;
             }
         }
-        Some(best_child.clone())
+        Some((best_child.0, best_child.1.clone()))
     }
 
-    /// Select and return the "best" child
-    fn select(&mut self, g: &mut Game<A>, c: f64) -> Option<Rc<RefCell<MCTSNode<A>>>> {
-        let mut prev_rc = None;
-        let mut node_rc = match self.status {
-            NodeStatus::Done => None,
-            NodeStatus::Expandable => None,
-            NodeStatus::Expanded => self.select_uct(c),
-        };
-        while let Some(n) = node_rc {
-            let node = n.borrow_mut();
-            g.make_move(node.play.unwrap());
-            prev_rc = Some(n.clone());
-            node_rc = match node.status {
-                NodeStatus::Done |
-                NodeStatus::Expandable => None,
-                NodeStatus::Expanded => node.select_uct(c),
+    /// Walk down from `root` by UCT, playing every move on the way, until
+    /// reaching a node that isn't fully expanded. Returns the path taken,
+    /// root first; since nodes may have several parents, this is the only
+    /// way back up. Moves are appended to `played` along with who made
+    /// them.
+    fn select(root: &Rc<RefCell<MCTSNode<A>>>, g: &mut dyn Game<A>, c: f64, rave: f64,
+              played: &mut Vec<(PunterId, A)>) -> Vec<Rc<RefCell<MCTSNode<A>>>> {
+        let mut path = vec![root.clone()];
+        loop {
+            let next = {
+                let node = path.last().unwrap().borrow();
+                match node.status {
+                    NodeStatus::Done |
+                    NodeStatus::Expandable => None,
+                    NodeStatus::Expanded => node.select_uct(c, rave),
+                }
             };
+            match next {
+                Some((play, child)) => {
+                    played.push((g.current_player(), play));
+                    g.make_move(play);
+                    path.push(child);
+                }
+                None => return path,
+            }
         }
-        prev_rc
     }
 
-    /// Expand and return a new child of this node.
-    fn expand(&mut self, g: &Game<A>) -> Option<Rc<RefCell<MCTSNode<A>>>> {
-        // eprintln!("Expanding: {:#?}", self);
+    /// Pick a move that hasn't been tried from this node yet. Moves in
+    /// `prior` are tried before any others.
+    fn expand(&mut self, g: &dyn Game<A>, prior: &HashSet<A>) -> Option<A> {
         let moves = g.available_actions();
-        if moves.len() == 0 {
+        if moves.is_empty() {
             self.status = NodeStatus::Done;
             return None;
         }
 
-        let mut available_moves = moves.clone();
+        let mut available_moves = moves.iter().cloned().collect::<HashSet<_>>();
         // Remove the children's moves from the available set
-        for child in &self.children {
-            let child_move = child.borrow().play.unwrap();
+        for &(child_move, _) in &self.children {
             available_moves.remove(&child_move);
         }
 
@@ -604,102 +1446,272 @@ impl<'a, A: GameAction> MCTSNode<A> {
         if available_moves.len() == 1 {
             self.status = NodeStatus::Expanded;
         }
-        assert!(available_moves.len() > 0);
+        assert!(!available_moves.is_empty());
 
         let mut rng = thread_rng();
-        let idx = rng.gen_range(0, available_moves.len());
-        let new_child_move = available_moves.iter().nth(idx);
-        let new_node = Rc::new(RefCell::new(MCTSNode::new(new_child_move.map(|x| *x))));
-        self.children.push(new_node.clone());
-        Some(new_node.clone())
+        let preferred = available_moves.iter()
+            .filter(|m| prior.contains(m))
+            .cloned()
+            .collect::<Vec<_>>();
+        if preferred.is_empty() {
+            let idx = rng.gen_range(0, available_moves.len());
+            available_moves.iter().nth(idx).cloned()
+        } else {
+            rng.choose(&preferred).cloned()
+        }
     }
 
-    /// Run a simulation from this node with the given game state. Currently a
-    /// pure Monte Carlo simulation.
-    fn simulate(&self, g: &mut Game<A>) -> f64 {
+    /// Run a simulation from the given game state to the end of the game.
+    /// Currently a pure Monte Carlo simulation. Moves are appended to
+    /// `played` along with who made them.
+    fn simulate(g: &mut dyn Game<A>, played: &mut Vec<(PunterId, A)>) -> f64 {
         let mut rng = thread_rng();
-        for _ in 0..SIMULATION_DEPTH {
-            let chosen_river = {
-                let choices = g.available_actions();
-                if choices.len() == 0 {
-                    break;
-                }
-                let rand_idx = rng.gen_range(0, choices.len());
-                *choices.iter().nth(rand_idx).unwrap()
-            };
+        while let Some(chosen_river) = g.rollout_move(&mut rng) {
+            played.push((g.current_player(), chosen_river));
             g.make_move(chosen_river);
         }
         g.score()
     }
 
-    fn backpropagate(&mut self, score: f64) {
-        self.count += 1.;
-        self.score += score;
-        let mut cur_node = self.parent.clone();
-        while let Some(weak_ref) = cur_node {
-            let node = weak_ref.upgrade().unwrap();
-            let mut node_ref = node.borrow_mut();
-            node_ref.count += 1.;
-            node_ref.score += score;
-            cur_node = node_ref.parent.clone();
+    // Credit the playout to every move the punter to move here made from
+    // here on. `played` starts with the move made from this node.
+    fn update_amaf(&mut self, played: &[(PunterId, A)], score: f64) {
+        let mover = match played.first() {
+            Some(&(mover, _)) => mover,
+            None => return,
+        };
+        for &(player, play) in played {
+            if player == mover {
+                let stats = self.amaf.entry(play).or_insert((0., 0.));
+                stats.0 += score;
+                stats.1 += 1.;
+            }
         }
     }
 
-    fn best_move(&self) -> A {
-        let mut best = self.play;
-        let mut best_value = NEG_INFINITY;
-        for child in &self.children {
-            let child_ref = child.borrow();
+    fn best_move(&self) -> Option<A> {
+        let mut best = None;
+        let mut best_value = f64::NEG_INFINITY;
+        for &(play, ref child) in &self.children {
             // TODO: shouldn't the value here be something like
             // child.score/child.count (average score)
             // SJC: not according to wikipedia...
-            let child_value = child_ref.count;
+            let child_value = child.borrow().count;
             if child_value > best_value {
                 best_value = child_value;
-                best = child_ref.play;
+                best = Some(play);
             }
         }
-        best.unwrap()
+        best
     }
 }
 
 #[derive(Debug)]
-struct MCTS<'a> {
+struct Mcts<'a> {
     punter: &'a Punter,
     root: Rc<RefCell<MCTSNode<RiverIdx>>>,
     c: f64,
+    // Rivers to try first when expanding a node
+    prior: HashSet<RiverIdx>,
+    // Every node below the root by position hash, so that claim orders
+    // reaching the same ownership share one node. None for a plain tree.
+    transpositions: Option<HashMap<u64, Rc<RefCell<MCTSNode<RiverIdx>>>>>,
+    nodes: usize,
+    peak_nodes: usize,
+    depth: usize,
+    // Claims made by all iterations together
+    claims: usize,
 }
 
-impl<'a> MCTS<'a> {
-    fn new(punter: &Punter, c: f64) -> MCTS {
-        MCTS {
-            punter: punter,
-            root: Rc::new(RefCell::new(MCTSNode::new(None))),
-            c: c,
+impl<'a> Mcts<'a> {
+    fn new(punter: &Punter, c: f64) -> Mcts<'_> {
+        let prior = if punter.config.planner_prior {
+            Plan::new(punter).rivers.into_iter().collect()
+        } else {
+            HashSet::new()
+        };
+        let transpositions = if punter.config.transpositions {
+            Some(HashMap::new())
+        } else {
+            None
+        };
+        Mcts {
+            punter,
+            root: Rc::new(RefCell::new(MCTSNode::new())),
+            c,
+            prior,
+            transpositions,
+            nodes: 1,
+            peak_nodes: 1,
+            depth: 0,
+            claims: 0,
         }
     }
 
     fn step(&mut self, game: &mut InternalGameState) {
-        let leaf = self.root.borrow_mut().select(game, self.c).unwrap_or(self.root.clone());
-        let new_child = leaf.borrow_mut().expand(game);
-        if let Some(child) = new_child {
-            let mut child_ref = child.borrow_mut();
-            child_ref.parent = Some(Rc::downgrade(&leaf));
-            let score = child_ref.simulate(game);
-            child_ref.backpropagate(score);
-        } else {
-            // If we couldn't expand, that means the leaf is terminal
-            // In that case, just backpropagate its score up
-            let mut leaf_ref = leaf.borrow_mut();
-            assert!(leaf_ref.status == NodeStatus::Done);
-            let leaf_score = leaf_ref.score;
-            leaf_ref.backpropagate(leaf_score);
+        let rave = self.punter.config.rave_equivalence;
+        let mut played = vec![];
+        let mut path = MCTSNode::select(&self.root, game, self.c, rave, &mut played);
+        let leaf = path.last().unwrap().clone();
+        let play = leaf.borrow_mut().expand(game, &self.prior);
+        let score = match play {
+            Some(play) => {
+                played.push((game.current_player(), play));
+                game.make_move(play);
+                let nodes = &mut self.nodes;
+                let mut new_node = || {
+                    *nodes += 1;
+                    Rc::new(RefCell::new(MCTSNode::new()))
+                };
+                let child = match self.transpositions {
+                    Some(ref mut table) => table.entry(game.position_hash())
+                        .or_insert_with(new_node)
+                        .clone(),
+                    None => new_node(),
+                };
+                leaf.borrow_mut().children.push((play, child.clone()));
+                path.push(child);
+                MCTSNode::simulate(game, &mut played)
+            }
+            // Nothing left to search from the leaf. The game may still go
+            // on beyond the rivers the tree considers, so play it out; at
+            // the real end, that's just the exact score.
+            None => MCTSNode::simulate(game, &mut played),
+        };
+        self.depth = self.depth.max(path.len() - 1);
+        self.claims += played.len();
+        for (depth, node) in path.iter().enumerate() {
+            let mut node_ref = node.borrow_mut();
+            node_ref.count += 1.;
+            node_ref.score += score;
+            if rave > 0. {
+                node_ref.update_amaf(&played[depth.min(played.len())..], score);
+            }
+        }
+        self.peak_nodes = self.peak_nodes.max(self.nodes);
+        let max_nodes = self.punter.config.max_nodes;
+        if max_nodes > 0 && self.nodes > max_nodes {
+            self.prune(max_nodes / 2);
+        }
+    }
+
+    // Drop the least visited nodes, leaving at most `keep`. Their parents
+    // can expand those moves again and keep the all-moves-as-first
+    // statistics they gathered. The root always stays.
+    fn prune(&mut self, keep: usize) {
+        let nodes = self.reachable();
+        let mut counts = nodes.iter().map(|node| node.borrow().count).collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let threshold = counts[keep.max(1).min(counts.len() - 1)];
+        for node in &nodes {
+            let mut node = node.borrow_mut();
+            let before = node.children.len();
+            node.children.retain(|(_, child)| child.borrow().count > threshold);
+            if node.children.len() < before && node.status == NodeStatus::Expanded {
+                node.status = NodeStatus::Expandable;
+            }
+        }
+
+        let live = self.reachable();
+        if let Some(ref mut table) = self.transpositions {
+            let live = live.iter().map(|node| node.as_ptr()).collect::<HashSet<_>>();
+            table.retain(|_, node| live.contains(&node.as_ptr()));
+        }
+        debug!("search", "Pruned the tree from {} to {} nodes", self.nodes, live.len());
+        self.nodes = live.len();
+    }
+
+    // Every node once, in breadth-first order from the root
+    fn reachable(&self) -> Vec<Rc<RefCell<MCTSNode<RiverIdx>>>> {
+        let mut seen = HashSet::new();
+        let mut order = vec![self.root.clone()];
+        seen.insert(self.root.as_ptr());
+        let mut next = 0;
+        while next < order.len() {
+            let node = order[next].clone();
+            for (_, child) in &node.borrow().children {
+                if seen.insert(child.as_ptr()) {
+                    order.push(child.clone());
+                }
+            }
+            next += 1;
+        }
+        order
+    }
+
+    fn best_move(&self) -> Option<Play> {
+        self.root.borrow().best_move()
+            .map(|ridx| Play::new(self.punter.river(ridx), self.punter.id()))
+    }
+
+    fn stats(&self, diagnostics: &Diagnostics, iterations: usize,
+             elapsed: Duration) -> SearchStats {
+        let mut children = self.root.borrow().children.iter()
+            .map(|&(ridx, ref child)| {
+                let child = child.borrow();
+                let river = self.punter.river(ridx);
+                ChildStats {
+                    source: river.source,
+                    target: river.target,
+                    visits: child.count as u64,
+                    mean: child.score / child.count,
+                }
+            })
+            .collect::<Vec<_>>();
+        children.sort_by_key(|child| Reverse(child.visits));
+        children.truncate(diagnostics.top_k);
+        SearchStats {
+            iterations,
+            elapsed_ms: elapsed.as_secs_f64() * 1000.,
+            depth: self.depth,
+            nodes: self.nodes,
+            peak_nodes: self.peak_nodes,
+            playout_claims: self.claims as f64 / iterations as f64,
+            children,
+            tree: diagnostics.tree.map(|format| self.dump(format)),
         }
     }
 
-    fn best_move(&self) -> Play {
-        let river = &self.punter.river(self.root.borrow().best_move());
-        Play::new(river, self.punter.id())
+    // Every node once, numbered in breadth-first order from the root, and
+    // every edge between them labelled with its river
+    fn dump(&self, format: TreeFormat) -> String {
+        let order = self.reachable();
+        let ids = order.iter().enumerate()
+            .map(|(id, node)| (node.as_ptr(), id))
+            .collect::<HashMap<_, _>>();
+        let mut edges = vec![];
+        for (id, node) in order.iter().enumerate() {
+            for &(ridx, ref child) in &node.borrow().children {
+                edges.push((id, ids[&child.as_ptr()], self.punter.river(ridx)));
+            }
+        }
+
+        match format {
+            TreeFormat::Json => {
+                let nodes = order.iter().enumerate().map(|(id, node)| {
+                    let node = node.borrow();
+                    json!({"id": id, "visits": node.count, "mean": node.score / node.count})
+                }).collect::<Vec<_>>();
+                let edges = edges.iter().map(|&(from, to, river)| {
+                    json!({"from": from, "to": to, "source": river.source, "target": river.target})
+                }).collect::<Vec<_>>();
+                json!({"nodes": nodes, "edges": edges}).to_string()
+            }
+            TreeFormat::Dot => {
+                let mut dot = String::from("digraph mcts {\n");
+                for (id, node) in order.iter().enumerate() {
+                    let node = node.borrow();
+                    dot += &format!("    n{} [label=\"n={}\\nmean={:.2}\"];\n",
+                                    id, node.count, node.score / node.count);
+                }
+                for &(from, to, river) in &edges {
+                    dot += &format!("    n{} -> n{} [label=\"{}-{}\"];\n",
+                                    from, to, river.source, river.target);
+                }
+                dot += "}\n";
+                dot
+            }
+        }
     }
 }
 
@@ -0,0 +1,126 @@
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use game::GameState;
+use protocol::{self, GameRecord};
+use punter::PunterId;
+
+const SIZE: f64 = 800.;
+const MARGIN: f64 = 40.;
+// Room at the top for the turn and the scores
+const HEADER: f64 = 20.;
+const COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231",
+    "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+
+/// The game after the first `turns` messages of a recorded game, or after
+/// the whole game if it has fewer. Fails on moves the rules don't allow.
+pub fn replay(record: &GameRecord, turns: usize) -> Result<GameState, String> {
+    let setup = &record.setup;
+    let mut game = GameState::new(setup.punters, setup.map.clone(), setup.settings.clone());
+    for (turn, moves) in record.turns.iter().take(turns).enumerate() {
+        // The server fills in a pass for everyone who hadn't moved before
+        // the first turn
+        let mut moves = moves.iter()
+            .filter(|m| match **m {
+                protocol::Move::pass (ref pass) => turn > 0 || pass.punter < setup.punter,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        // Every message lists the last move of each punter, by punter id.
+        // Put them in the order they were made; the stop message repeats
+        // the moves of those who had no move left, so only the first ones
+        // are new.
+        let (first, punters) = (game.next_punter(), game.punters());
+        moves.sort_by_key(|m| (m.punter() + punters - first) % punters);
+        let left = game.total_moves() - game.moves();
+        for m in moves.into_iter().take(left) {
+            game.apply(m).map_err(|e| format!("Turn {}: {:?}: {}", turn + 1, m, e))?;
+        }
+    }
+    Ok(game)
+}
+
+pub fn punter_color(punter: PunterId) -> String {
+    match COLORS.get(punter) {
+        Some(color) => color.to_string(),
+        // Spread the rest around the color wheel
+        None => format!("hsl({}, 70%, 45%)", (punter * 137) % 360),
+    }
+}
+
+/// Draw the map: free rivers in grey, claimed ones in
+/// their owner's color, optioned ones with a dashed line in the renter's
+/// color on top, mines as big red dots. Sites are placed at their map
+/// coordinates, or around a circle if the map has none.
+pub fn render_svg(game: &GameState) -> String {
+    let positions = layout(game);
+    let mut svg = String::new();
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+             SIZE, SIZE + HEADER).unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+
+    let scores = game.scores();
+    write!(svg, r#"<text x="10" y="16" font-family="sans-serif" font-size="14">Move {} of {}"#,
+           game.moves(), game.total_moves()).unwrap();
+    for (p, score) in scores.iter().enumerate() {
+        write!(svg, r#" <tspan fill="{}">punter {}: {}</tspan>"#, punter_color(p), p, score).unwrap();
+    }
+    writeln!(svg, "</text>").unwrap();
+
+    for river in &game.map.rivers {
+        let (x1, y1) = positions[river.source_idx];
+        let (x2, y2) = positions[river.target_idx];
+        let (class, color, width) = match river.owner {
+            Some(owner) => ("river owned", punter_color(owner), 4),
+            None => ("river free", "#cccccc".to_string(), 1),
+        };
+        writeln!(svg, r#"<line class="{}" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{}"/>"#,
+                 class, x1, y1, x2, y2, color, width).unwrap();
+        if let Some(renter) = river.renter {
+            writeln!(svg, r#"<line class="river rented" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2" stroke-dasharray="6,4"/>"#,
+                     x1, y1, x2, y2, punter_color(renter)).unwrap();
+        }
+    }
+
+    for (idx, site) in game.map.sites.iter().enumerate() {
+        let (x, y) = positions[idx];
+        let (class, radius, color) = if game.mines.contains(&idx) {
+            ("mine", 8, "red")
+        } else {
+            ("site", 4, "black")
+        };
+        writeln!(svg, r#"<circle class="{}" cx="{:.1}" cy="{:.1}" r="{}" fill="{}"><title>{}</title></circle>"#,
+                 class, x, y, radius, color, site.id).unwrap();
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+// Canvas position of every site, by site index
+fn layout(game: &GameState) -> Vec<(f64, f64)> {
+    let sites = &game.map.sites;
+    let coords = sites.iter()
+        .map(|site| site.x.and_then(|x| site.y.map(|y| (x, y))))
+        .collect::<Option<Vec<_>>>();
+    let coords = coords.unwrap_or_else(|| {
+        (0..sites.len()).map(|idx| {
+            let angle = 2. * PI * idx as f64 / sites.len() as f64;
+            (angle.cos(), angle.sin())
+        }).collect()
+    });
+
+    let bounds = |select: fn(&(f64, f64)) -> f64| {
+        coords.iter().map(select).fold((f64::INFINITY, f64::NEG_INFINITY),
+                                       |(lo, hi), v| (lo.min(v), hi.max(v)))
+    };
+    let (min_x, max_x) = bounds(|c| c.0);
+    let (min_y, max_y) = bounds(|c| c.1);
+    // Keep the aspect ratio, and don't divide by zero on single-site maps
+    let span = (max_x - min_x).max(max_y - min_y).max(f64::EPSILON);
+    let scale = (SIZE - 2. * MARGIN) / span;
+    coords.iter()
+        .map(|&(x, y)| (MARGIN + (x - min_x) * scale, HEADER + MARGIN + (y - min_y) * scale))
+        .collect()
+}
@@ -0,0 +1,66 @@
+// Cut detection on the sample map, for ourselves and for an opponent.
+extern crate punter;
+extern crate serde_json;
+
+use punter::analysis::Criticality;
+use punter::punter::{Punter, PunterType};
+
+mod common;
+use common::{claim, pass, sample_punter};
+
+fn river(punter: &Punter, source: usize, target: usize) -> usize {
+    (0..punter.num_rivers())
+        .find(|r| {
+            let sites = punter.river_sites(*r);
+            sites == (source, target) || sites == (target, source)
+        })
+        .unwrap()
+}
+
+// Punter 0 walls in mine 1 so that we (punter 1, holding 5-7) can only reach
+// it through 1-7
+fn walled_in() -> Punter {
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&[claim(0, 1, 3), claim(1, 5, 7)]);
+    punter.process_turn(&[claim(0, 1, 2), pass(1)]);
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    punter
+}
+
+#[test]
+fn bridges_and_cuts_for_us() {
+    let punter = walled_in();
+    let analysis = Criticality::new(&punter, 1);
+
+    let mut bridges = analysis.bridges.iter()
+        .map(|r| punter.river_sites(*r))
+        .collect::<Vec<_>>();
+    bridges.sort();
+    assert_eq!(bridges, vec![(0, 7), (1, 7), (2, 3)]);
+
+    let to_mine = analysis.cuts.iter().find(|cut| cut.target == 1).unwrap();
+    assert_eq!(to_mine.rivers, vec![river(&punter, 1, 7)]);
+    // Mine 5 reaches mine 1 at distance 2, mine 1 reaches 5 and 7
+    assert_eq!(to_mine.value, 4 + 4 + 1);
+    assert_eq!(analysis.most_critical(), Some(river(&punter, 1, 7)));
+
+    // Rivers we hold or can't use are never critical
+    assert_eq!(analysis.scores[river(&punter, 5, 7)], 0.);
+    assert_eq!(analysis.scores[river(&punter, 1, 3)], 0.);
+}
+
+#[test]
+fn well_connected_targets_have_no_cut() {
+    let punter = walled_in();
+    let analysis = Criticality::new(&punter, 0);
+    assert!(analysis.bridges.is_empty());
+    // Three separate ways lead from mine 1 to mine 5
+    assert!(analysis.cuts.iter().all(|cut| cut.target != 5));
+    // Site 4 only has two rivers, and both are free
+    let to_four = analysis.cuts.iter().find(|cut| cut.target == 4).unwrap();
+    let mut rivers = to_four.rivers.clone();
+    rivers.sort();
+    let mut expected = vec![river(&punter, 3, 4), river(&punter, 4, 5)];
+    expected.sort();
+    assert_eq!(rivers, expected);
+}
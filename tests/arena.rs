@@ -0,0 +1,111 @@
+// Local games between our AIs, offline-protocol bots and scripted players.
+extern crate punter;
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use punter::arena;
+use punter::player::{Builtin, External, Player};
+use punter::protocol::{self, Move, Score};
+use punter::punter::{Input, PunterType};
+use punter::render;
+
+const SETUP: &str = include_str!("transcripts/setup.json");
+
+fn play(mut players: Vec<Box<dyn Player>>) -> arena::GameResult {
+    let setup: serde_json::Value = serde_json::from_str(SETUP).unwrap();
+    let map = serde_json::from_value(setup["map"].clone()).unwrap();
+    arena::play_game(&map, &Default::default(), &mut players, 0)
+}
+
+#[test]
+fn builtin_players_take_every_river() {
+    let result = play(vec![Box::new(Builtin::new(PunterType::Greedy)),
+                           Box::new(Builtin::new(PunterType::Random))]);
+    assert_eq!(result.forfeits, vec![0, 0]);
+    // Six turns for punter 0, then the stop
+    assert_eq!(result.record.turns.len(), 7);
+    let replayed = render::replay(&result.record, result.record.turns.len()).unwrap();
+    assert_eq!(replayed.scores(), result.scores);
+    assert!((0..replayed.num_rivers()).all(|r| replayed.river_owner(r).is_some()));
+}
+
+#[test]
+fn offline_bot_plays_through_the_adapter() {
+    let bot = External::new(env!("CARGO_BIN_EXE_punter").to_string(),
+                            vec!["--ai".to_string(), "greedy".to_string()]);
+    let result = play(vec![Box::new(bot), Box::new(Builtin::new(PunterType::Random))]);
+    assert_eq!(result.forfeits, vec![0, 0]);
+    let replayed = render::replay(&result.record, result.record.turns.len()).unwrap();
+    assert_eq!(replayed.scores(), result.scores);
+    // Greedy links the mines on this map before random gets anywhere near
+    assert!(result.scores[0] > 0);
+}
+
+#[test]
+fn slow_bots_are_cut_off() {
+    // Sets up like any bot, then sleeps through every turn
+    let count = env::temp_dir().join(format!("punter-slow-{}", std::process::id()));
+    let script = format!("n=$(cat {count} 2>/dev/null || echo 0); echo $((n + 1)) > {count}; \
+                          if [ $n -ge 1 ] && [ $n -le 6 ]; then exec sleep 30; fi; \
+                          exec {punter} --ai greedy",
+                         count = count.display(), punter = env!("CARGO_BIN_EXE_punter"));
+    let slow = External::new("sh".to_string(), vec!["-c".to_string(), script]);
+    let setup: serde_json::Value = serde_json::from_str(SETUP).unwrap();
+    let map = serde_json::from_value(setup["map"].clone()).unwrap();
+    let mut players: Vec<Box<dyn Player>> = vec![Box::new(slow),
+                                                 Box::new(Builtin::new(PunterType::Greedy))];
+    let begin = Instant::now();
+    let result = arena::play_game(&map, &Default::default(), &mut players, 1);
+    fs::remove_file(&count).unwrap();
+    assert_eq!(result.forfeits, vec![6, 0]);
+    // Nobody waited for the sleeps to end
+    assert!(begin.elapsed() < Duration::from_secs(20), "{:?}", begin.elapsed());
+}
+
+#[test]
+fn hung_bots_are_cut_off_at_setup() {
+    let hung = External::new("sleep".to_string(), vec!["60".to_string()]);
+    let setup: serde_json::Value = serde_json::from_str(SETUP).unwrap();
+    let map = serde_json::from_value(setup["map"].clone()).unwrap();
+    let mut players: Vec<Box<dyn Player>> = vec![Box::new(hung),
+                                                 Box::new(Builtin::new(PunterType::Greedy))];
+    let begin = Instant::now();
+    // No move time limit, but setup has one all the same
+    let result = arena::play_game(&map, &Default::default(), &mut players, 0);
+    assert_eq!(result.forfeits, vec![6, 0]);
+    assert!(begin.elapsed() < Duration::from_secs(20), "{:?}", begin.elapsed());
+}
+
+// Claims the same river every turn
+struct Stubborn {
+    punter: usize,
+}
+
+impl Player for Stubborn {
+    fn setup(&mut self, input: &Input) -> Result<(), String> {
+        self.punter = input.punter();
+        Ok(())
+    }
+
+    fn play(&mut self, _moves: &[Move], _budget: Duration) -> Result<Move, String> {
+        Ok(Move::claim (protocol::Claim { punter: self.punter, source: 0, target: 1 }))
+    }
+
+    fn stop(&mut self, _moves: &[Move], _scores: &[Score]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[test]
+fn bad_moves_and_broken_bots_pass() {
+    let broken = External::from_command("/nonexistent/bot --fast").unwrap();
+    let result = play(vec![Box::new(Stubborn { punter: 0 }),
+                           Box::new(broken),
+                           Box::new(Builtin::new(PunterType::Random))]);
+    // Only the first claim of 0-1 counts, and the bot never starts
+    assert_eq!(result.forfeits, vec![3, 4, 0]);
+    assert_eq!(result.scores[1], 0);
+}
@@ -0,0 +1,345 @@
+// End-to-end tests: run the real binaries against a scripted fake server and
+// check every message they send back. Single games go over the binaries'
+// stdin and stdout; only sessions, which are about ports, use sockets.
+extern crate punter;
+extern crate serde_json;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use serde_json::Value;
+
+use punter::protocol;
+use punter::punter::{Input, Punter, PunterType};
+
+const SETUP: &str = include_str!("transcripts/setup.json");
+const TURNS: &str = include_str!("transcripts/turns.json");
+const ONLINE_GAME: &str = include_str!("transcripts/online_game.json");
+
+fn send<W: Write>(writer: &mut W, msg: &Value) {
+    let msg_str = serde_json::to_string(msg).unwrap();
+    writer.write_all(format!("{}:{}", msg_str.len(), msg_str).as_bytes()).unwrap();
+    writer.flush().unwrap();
+}
+
+fn recv<R: BufRead>(reader: &mut R) -> Value {
+    let mut buf = vec![];
+    reader.read_until(b':', &mut buf).unwrap();
+    buf.pop(); // Drop colon
+    let len = String::from_utf8(buf).unwrap()
+        .parse::<u64>().expect("Missing message length");
+    serde_json::from_reader(reader.take(len)).expect("Could not parse message")
+}
+
+/// Check that every field in `expected` is present in `actual` with the same
+/// value; `actual` may contain more.
+fn assert_matches(actual: &Value, expected: &Value) {
+    match (actual, expected) {
+        (Value::Object(actual_fields), Value::Object(expected_fields)) => {
+            for (key, value) in expected_fields {
+                match actual_fields.get(key) {
+                    Some(actual_value) => assert_matches(actual_value, value),
+                    None => panic!("Missing {} in {}", key, actual),
+                }
+            }
+        }
+        _ => assert_eq!(actual, expected),
+    }
+}
+
+/// Replace `"$last"` placeholders in a scripted server message with the last
+/// move the punter sent.
+fn fill_in(msg: &Value, last: &Value) -> Value {
+    match *msg {
+        Value::String(ref s) if s == "$last" => last.clone(),
+        Value::Array(ref values) => Value::Array(values.iter().map(|v| fill_in(v, last)).collect()),
+        Value::Object(ref fields) => Value::Object(fields.iter()
+            .map(|(k, v)| (k.clone(), fill_in(v, last)))
+            .collect()),
+        _ => msg.clone(),
+    }
+}
+
+/// Play the scripted online game with the first client that connects
+fn serve_script(listener: &TcpListener) {
+    let (stream, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    play_script(&mut reader, &mut writer);
+}
+
+/// Play the scripted online game with a client started with --stdio, over
+/// its pipes. Returns once the client has exited.
+fn serve_script_stdio(mut client: Child) {
+    let mut reader = BufReader::new(client.stdout.take().unwrap());
+    play_script(&mut reader, &mut client.stdin.take().unwrap());
+    assert!(client.wait().unwrap().success());
+}
+
+/// Play the server's side of the scripted online game, checking every
+/// message from the punter
+fn play_script<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) {
+    let script: Value = serde_json::from_str(ONLINE_GAME).unwrap();
+    let mut last = Value::Null;
+    for step in script.as_array().unwrap() {
+        match step["from"].as_str().unwrap() {
+            "server" => {
+                let msg = match step["msg"] {
+                    Value::String(_) => serde_json::from_str(SETUP).unwrap(),
+                    ref msg => fill_in(msg, &last),
+                };
+                send(writer, &msg);
+            }
+            "punter" => {
+                let msg = recv(reader);
+                assert_matches(&msg, &step["msg"]);
+                let _: protocol::Move = match msg.get("ready").or_else(|| msg.get("me")) {
+                    Some(_) => continue,
+                    None => serde_json::from_value(msg.clone()).unwrap(),
+                };
+                last = msg;
+            }
+            from => panic!("Unknown message source {}", from),
+        }
+    }
+}
+
+#[test]
+fn online_game() {
+    let client = Command::new(env!("CARGO_BIN_EXE_online"))
+        .arg("--stdio")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    serve_script_stdio(client);
+}
+
+#[test]
+fn record_and_render_online_game() {
+    let dir = env::temp_dir().join(format!("punter-record-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let client = Command::new(env!("CARGO_BIN_EXE_online"))
+        .args(["--stdio", "--record", dir.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    serve_script_stdio(client);
+
+    let game = dir.join("game-0.json");
+    let record: protocol::GameRecord = serde_json::from_reader(File::open(&game).unwrap()).unwrap();
+    let script: Value = serde_json::from_str(ONLINE_GAME).unwrap();
+    let server_turns = script.as_array().unwrap().iter()
+        .filter(|step| step["msg"].get("move").is_some() || step["msg"].get("stop").is_some())
+        .count();
+    assert_eq!(record.turns.len(), server_turns);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_render"))
+        .args(["--turn", "1", game.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let svg = String::from_utf8(output.stdout).unwrap();
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("Move 1 of 12"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// What the session summary calls the scripted game's map
+fn script_map() -> String {
+    let input: Input = serde_json::from_str(SETUP).unwrap();
+    format!("{:016x}", Punter::new(input, PunterType::Random).map_hash())
+}
+
+/// Run a session against one scripted server per port, `games` games each,
+/// and return the summary file contents.
+fn online_session(ports: usize, games: usize, args: &[&str], summary: &str) -> String {
+    let listeners = (0..ports)
+        .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect::<Vec<_>>();
+    let port_list = listeners.iter()
+        .map(|l| l.local_addr().unwrap().port().to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let summary_path = env::temp_dir()
+        .join(format!("punter-session-{}-{}", std::process::id(), summary));
+    let mut client = Command::new(env!("CARGO_BIN_EXE_online"))
+        .args(["-s", "127.0.0.1", "--ports", &port_list, "-g", &games.to_string(),
+               "--summary", summary_path.to_str().unwrap()])
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let servers = listeners.into_iter().map(|listener| thread::spawn(move || {
+        for _ in 0..games {
+            serve_script(&listener);
+        }
+    })).collect::<Vec<_>>();
+    for server in servers {
+        server.join().unwrap();
+    }
+    assert!(client.wait().unwrap().success());
+
+    let mut contents = String::new();
+    File::open(&summary_path).unwrap().read_to_string(&mut contents).unwrap();
+    fs::remove_file(&summary_path).unwrap();
+    contents
+}
+
+#[test]
+fn online_session_parallel_json() {
+    let summary: Value = serde_json::from_str(
+        &online_session(2, 1, &["--parallel"], "summary.json")).unwrap();
+    let games = summary.as_array().unwrap();
+    assert_eq!(games.len(), 2);
+    for game in games {
+        assert_matches(game, &serde_json::json!({
+            "server": "127.0.0.1",
+            "map": script_map(),
+            "sites": 8,
+            "rivers": 12,
+            "punters": 2,
+            "punter": 1,
+            "rank": 2,
+            "score": 0,
+            "moves": 2,
+        }));
+        assert!(game["avg_move_ms"].as_f64().unwrap() > 0.);
+    }
+}
+
+#[test]
+fn online_session_sequential_csv() {
+    let summary = online_session(1, 2, &[], "summary.csv");
+    let lines = summary.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "server,port,map,sites,rivers,punters,punter,rank,score,moves,avg_move_ms");
+    for line in &lines[1..] {
+        let fields = line.split(',').collect::<Vec<_>>();
+        assert_eq!(fields[0], "127.0.0.1");
+        assert_eq!(fields[2], script_map());
+        assert_eq!(&fields[3..10], &["8", "12", "2", "1", "2", "0", "2"]);
+    }
+}
+
+/// Run a single offline step: handshake, one message, and collect the reply
+/// (if any).
+fn offline_step(msg: &Value) -> Option<Value> {
+    offline_step_with(msg, &[])
+}
+
+fn offline_step_with(msg: &Value, args: &[&str]) -> Option<Value> {
+    let mut punter = Command::new(env!("CARGO_BIN_EXE_punter"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(punter.stdout.take().unwrap());
+    {
+        let stdin = punter.stdin.as_mut().unwrap();
+        assert_eq!(recv(&mut stdout), serde_json::json!({"me": "random hackers"}));
+        send(stdin, &serde_json::json!({"you": "random hackers"}));
+        send(stdin, msg);
+    }
+    let reply = {
+        let mut rest = vec![];
+        stdout.read_to_end(&mut rest).unwrap();
+        if rest.is_empty() {
+            None
+        } else {
+            Some(recv(&mut &rest[..]))
+        }
+    };
+    assert!(punter.wait().unwrap().success());
+    reply
+}
+
+#[test]
+fn offline_game() {
+    let ready = offline_step(&serde_json::from_str(SETUP).unwrap())
+        .expect("No ready message");
+    assert_eq!(ready["ready"], 1);
+    let state = ready["state"].clone();
+    let _: protocol::OfflineReadyP = serde_json::from_value(ready).unwrap();
+
+    let turns: Value = serde_json::from_str(TURNS).unwrap();
+    let mut turn = turns[1].clone();
+    turn.as_object_mut().unwrap().insert("state".to_string(), state.clone());
+    let reply: protocol::OfflineMove = serde_json::from_value(offline_step(&turn).expect("No move"))
+        .unwrap();
+    match reply {
+        protocol::OfflineMove::Claim (claim) => {
+            assert_eq!(claim.claim.punter, 1);
+            assert_eq!(claim.state.id(), 1);
+        }
+        m => panic!("Expected claim, got {:?}", m),
+    }
+
+    assert_eq!(offline_step(&turns[3]), None);
+
+    let mut stop = turns[4].clone();
+    stop.as_object_mut().unwrap().insert("state".to_string(), state);
+    assert_eq!(offline_step(&stop), None);
+}
+
+#[test]
+fn offline_turn_log() {
+    let ready = offline_step(&serde_json::from_str(SETUP).unwrap()).unwrap();
+    let turns: Value = serde_json::from_str(TURNS).unwrap();
+    let mut turn = turns[1].clone();
+    turn.as_object_mut().unwrap().insert("state".to_string(), ready["state"].clone());
+
+    let log_path = env::temp_dir()
+        .join(format!("punter-turn-log-{}.log", std::process::id()));
+    offline_step_with(&turn, &["--log", "off,turn=info", "--log-file", log_path.to_str().unwrap()])
+        .expect("No move");
+    let mut log = String::new();
+    File::open(&log_path).unwrap().read_to_string(&mut log).unwrap();
+    fs::remove_file(&log_path).unwrap();
+
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    let record: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(record["target"], "turn");
+    assert_eq!(record["level"], "info");
+    assert_eq!(record["turn"], 1);
+    assert_eq!(record["move"]["claim"]["punter"], 1);
+    assert!(record["budget_ms"].as_f64().unwrap() > 0.);
+    assert!(record["elapsed_ms"].as_f64().is_some());
+    assert!(record["iterations"].as_u64().is_some());
+    assert!(record["peak_nodes"].as_u64().unwrap() >= 1);
+}
+
+#[test]
+fn local_tournament_with_offline_bot() {
+    let map = env::temp_dir().join(format!("punter-local-map-{}.json", std::process::id()));
+    fs::write(&map, SETUP).unwrap();
+    let bot = format!("{} --ai greedy", env!("CARGO_BIN_EXE_punter"));
+    let output = Command::new(env!("CARGO_BIN_EXE_local"))
+        .args(["-g", "2", "-t", "0", map.to_str().unwrap(), "random", &bot])
+        .stderr(Stdio::null())
+        .output()
+        .unwrap();
+    fs::remove_file(&map).unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    // Seats swap between games
+    assert!(lines[0].starts_with("Game 0: random "));
+    assert!(lines[1].starts_with(&format!("Game 1: {} ", bot)));
+    assert!(!stdout.contains("forfeited"));
+    assert!(lines[3].starts_with(&format!("{}: ", bot)));
+}
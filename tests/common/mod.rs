@@ -0,0 +1,51 @@
+// Fixtures and move builders shared by the integration tests. Every test
+// crate only uses some of them.
+#![allow(dead_code)]
+
+use serde_json;
+
+use punter::game::GameState;
+use punter::protocol::{self, Move};
+use punter::punter::{Input, Punter, PunterType};
+
+pub const SETUP: &str = include_str!("../transcripts/setup.json");
+pub const SETUP_SETTINGS: &str = include_str!("../transcripts/setup_settings.json");
+
+pub fn punter_on(setup: &str, ai: PunterType) -> Punter {
+    let input: Input = serde_json::from_str(setup).unwrap();
+    Punter::new(input, ai)
+}
+
+/// Punter 0 of 2 on the sample map
+pub fn sample_punter(ai: PunterType) -> Punter {
+    punter_on(SETUP, ai)
+}
+
+/// Punter 0 of 3 on a square with two mines, every setting enabled
+pub fn square_punter() -> Punter {
+    punter_on(SETUP_SETTINGS, PunterType::Random)
+}
+
+/// The rules alone for a setup message
+pub fn game(setup: &str) -> GameState {
+    let input: serde_json::Value = serde_json::from_str(setup).unwrap();
+    GameState::new(input["punters"].as_u64().unwrap() as usize,
+                   serde_json::from_value(input["map"].clone()).unwrap(),
+                   serde_json::from_value(input["settings"].clone()).unwrap_or_default())
+}
+
+pub fn claim(punter: usize, source: usize, target: usize) -> Move {
+    Move::claim (protocol::Claim { punter, source, target })
+}
+
+pub fn option(punter: usize, source: usize, target: usize) -> Move {
+    Move::option (protocol::Claim { punter, source, target })
+}
+
+pub fn pass(punter: usize) -> Move {
+    Move::pass (protocol::Pass { punter })
+}
+
+pub fn splurge(punter: usize, route: Vec<usize>) -> Move {
+    Move::splurge (protocol::Splurge { punter, route })
+}
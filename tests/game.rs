@@ -0,0 +1,104 @@
+// The rules of the game on their own, without any AI.
+extern crate punter;
+extern crate serde_json;
+
+use punter::protocol;
+use punter::punter::PunterType;
+
+mod common;
+use common::{claim, game, option, pass, sample_punter, splurge, SETUP, SETUP_SETTINGS};
+
+// The same square with a tail off site 3, so that the game lasts long
+// enough to bank passes for a splurge
+const TAILED_SQUARE: &str = r#"{"punter": 0, "punters": 3, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}, {"id": 6}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+               {"source": 2, "target": 3}, {"source": 3, "target": 0},
+               {"source": 3, "target": 4}, {"source": 4, "target": 5}, {"source": 5, "target": 6}],
+    "mines": [0, 2]},
+    "settings": {"futures": true, "splurges": true, "options": true}}"#;
+
+#[test]
+fn scores_agree_with_the_punter() {
+    let mut state = game(SETUP);
+    let moves = vec![claim(0, 3, 5), claim(1, 1, 3), claim(0, 5, 7), claim(1, 0, 1)];
+    for m in &moves {
+        state.apply(m).unwrap();
+    }
+    assert_eq!(state.moves(), 4);
+    assert_eq!(state.scores(), vec![2, 2]);
+
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&moves);
+    assert_eq!(punter.scores(), vec![2, 2]);
+}
+
+#[test]
+fn illegal_moves_change_nothing() {
+    let mut state = game(SETUP);
+    state.apply(&claim(0, 3, 5)).unwrap();
+    // Taken, not a river, unknown site, unknown punter
+    assert!(state.apply(&claim(1, 5, 3)).is_err());
+    assert!(state.apply(&claim(1, 0, 5)).is_err());
+    assert!(state.apply(&claim(1, 0, 42)).is_err());
+    assert!(state.apply(&pass(2)).is_err());
+    // No options, splurges or futures without the settings
+    assert!(state.apply(&option(1, 3, 5)).is_err());
+    assert!(state.apply(&splurge(1, vec![0, 1])).is_err());
+    assert!(state.set_futures(1, vec![]).is_err());
+    assert_eq!(state.moves(), 1);
+    assert_eq!((0..state.num_rivers()).filter(|r| state.river_owner(*r).is_some()).count(), 1);
+}
+
+#[test]
+fn options_and_splurges_cost_credits() {
+    let mut state = game(TAILED_SQUARE);
+    state.apply(&claim(0, 0, 1)).unwrap();
+    assert_eq!(state.options_left(1), 2);
+    state.apply(&option(1, 0, 1)).unwrap();
+    assert_eq!(state.options_left(1), 1);
+    // Only one renter per river
+    assert!(state.apply(&option(2, 0, 1)).is_err());
+
+    // Three rivers need two banked passes
+    assert!(state.apply(&splurge(2, vec![1, 2, 3, 0])).is_err());
+    state.apply(&pass(2)).unwrap();
+    state.apply(&pass(2)).unwrap();
+    assert_eq!(state.credits(2), 2);
+    // A failed splurge hands back the rivers it took on the way
+    assert!(state.apply(&splurge(2, vec![1, 2, 3, 2])).is_err());
+    assert_eq!(state.river_owner(1), None);
+    assert_eq!(state.river_owner(2), None);
+    state.apply(&splurge(2, vec![1, 2, 3, 0])).unwrap();
+    assert_eq!(state.credits(2), 0);
+    assert_eq!(state.scores(), vec![1, 1, 12]);
+}
+
+#[test]
+fn futures_count_towards_scores() {
+    let mut state = game(SETUP_SETTINGS);
+    // Futures have to come from a mine and end elsewhere
+    assert!(state.set_futures(0, vec![protocol::Future { source: 1, target: 3 }]).is_err());
+    assert!(state.set_futures(0, vec![protocol::Future { source: 0, target: 2 }]).is_err());
+    state.set_futures(0, vec![protocol::Future { source: 0, target: 1 },
+                              protocol::Future { source: 2, target: 3 }]).unwrap();
+    state.apply(&claim(0, 0, 1)).unwrap();
+    assert_eq!(state.scores(), vec![1 + 1 - 1, 0, 0]);
+    assert!(state.set_futures(1, vec![]).is_err());
+}
+
+#[test]
+fn one_move_per_river() {
+    let mut state = game(SETUP_SETTINGS);
+    assert_eq!(state.next_punter(), 0);
+    state.apply(&claim(0, 0, 1)).unwrap();
+    state.apply(&pass(1)).unwrap();
+    assert_eq!(state.last_mover(), Some(1));
+    assert_eq!(state.next_punter(), 2);
+    state.apply(&pass(2)).unwrap();
+    state.apply(&pass(0)).unwrap();
+    // Rivers are left, but the game is over
+    assert!(state.is_over());
+    assert!(state.apply(&claim(1, 1, 2)).is_err());
+    assert_eq!(state.moves(), state.total_moves());
+}
@@ -0,0 +1,30 @@
+// Log spec parsing and filtering.
+extern crate punter;
+
+use punter::logging::{Level, LogSpec};
+
+#[test]
+fn default_level_and_overrides() {
+    let spec: LogSpec = "warn,search=debug,turn=off".parse().unwrap();
+    assert!(spec.enabled(Level::Error, "game"));
+    assert!(spec.enabled(Level::Warn, "game"));
+    assert!(!spec.enabled(Level::Info, "game"));
+    assert!(spec.enabled(Level::Debug, "search"));
+    assert!(!spec.enabled(Level::Trace, "search"));
+    assert!(!spec.enabled(Level::Error, "turn"));
+
+    // Targets alone keep the info default for everything else
+    let spec: LogSpec = "protocol=trace".parse().unwrap();
+    assert!(spec.enabled(Level::Info, "game"));
+    assert!(!spec.enabled(Level::Debug, "game"));
+    assert!(spec.enabled(Level::Trace, "protocol"));
+
+    let spec: LogSpec = "off".parse().unwrap();
+    assert!(!spec.enabled(Level::Error, "game"));
+}
+
+#[test]
+fn bad_specs() {
+    assert!("loud".parse::<LogSpec>().is_err());
+    assert!("search=verbose".parse::<LogSpec>().is_err());
+}
@@ -0,0 +1,86 @@
+// Steiner tree planner tests on the sample map
+extern crate punter;
+extern crate serde_json;
+
+use std::time::{Duration, Instant};
+
+use punter::planner::Plan;
+use punter::protocol;
+use punter::punter::{Punter, PunterType};
+
+mod common;
+use common::{claim, pass, sample_punter};
+
+/// Claim every river in the plan for us and return the sites our network
+/// then reaches from each mine.
+fn reached_after(punter: &mut Punter, plan: &Plan) -> Vec<Vec<usize>> {
+    let moves = plan.rivers.iter()
+        .map(|ridx| {
+            let (source, target) = punter.river_sites(*ridx);
+            claim(punter.id(), source, target)
+        })
+        .collect::<Vec<_>>();
+    punter.process_turn(&moves);
+    punter.score_breakdown()[punter.id()].mines.iter()
+        .map(|mine| mine.reachable.clone())
+        .collect()
+}
+
+#[test]
+fn plan_connects_mines_and_far_sites() {
+    let mut punter = sample_punter(PunterType::Steiner);
+    let plan = Plan::new(&punter);
+    let mut terminals = plan.terminals.clone();
+    terminals.sort();
+    assert_eq!(terminals, vec![0, 4, 5]);
+    assert!(plan.rivers.len() <= 4);
+
+    let reached = reached_after(&mut punter, &plan);
+    for site in &[0, 1, 4, 5] {
+        assert!(reached[0].contains(site), "site {} not connected", site);
+    }
+}
+
+#[test]
+fn plan_routes_around_opponents() {
+    let mut punter = sample_punter(PunterType::Steiner);
+    punter.process_turn(&[claim(0, 1, 3), claim(1, 0, 1)]);
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 2, 3)]);
+    punter.process_turn(&[claim(0, 1, 7), pass(1)]);
+
+    let plan = Plan::new(&punter);
+    assert!(plan.terminals.contains(&5));
+    for ridx in &plan.rivers {
+        assert_eq!(punter.river_owner(*ridx), None);
+    }
+    let reached = reached_after(&mut punter, &plan);
+    assert!(reached[0].contains(&5));
+}
+
+#[test]
+fn plan_is_empty_when_the_map_is_full() {
+    let mut punter = sample_punter(PunterType::Steiner);
+    let moves = (0..punter.num_rivers())
+        .map(|ridx| {
+            let (source, target) = punter.river_sites(ridx);
+            claim(0, source, target)
+        })
+        .collect::<Vec<_>>();
+    punter.process_turn(&moves);
+    let plan = Plan::new(&punter);
+    assert!(plan.rivers.is_empty());
+    assert!(plan.terminals.is_empty());
+}
+
+#[test]
+fn steiner_ai_claims_from_the_plan() {
+    let punter = sample_punter(PunterType::Steiner);
+    let plan = Plan::new(&punter);
+    let planned = plan.rivers.iter()
+        .map(|ridx| punter.river_sites(*ridx))
+        .collect::<Vec<_>>();
+    match punter.make_move(Instant::now(), Duration::from_secs(0)) {
+        protocol::Move::claim (c) => assert!(planned.contains(&(c.source, c.target))),
+        m => panic!("Expected claim, got {:?}", m),
+    }
+}
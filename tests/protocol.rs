@@ -0,0 +1,315 @@
+// Golden transcript tests: every message we can send or receive must survive a
+// trip through our protocol types without losing anything the server cares
+// about.
+extern crate punter;
+extern crate serde;
+extern crate serde_json;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use punter::protocol;
+use punter::punter::{Input, Punter, PunterType};
+
+const HANDSHAKE: &str = include_str!("transcripts/handshake.json");
+const SETUP: &str = include_str!("transcripts/setup.json");
+const SETUP_SETTINGS: &str = include_str!("transcripts/setup_settings.json");
+const TURNS: &str = include_str!("transcripts/turns.json");
+
+fn parse(json: &str) -> Value {
+    serde_json::from_str(json).expect("Could not parse transcript")
+}
+
+/// Decode `json` as a `T`, encode it again and check that a second round trip
+/// is stable, punter state included. Returns the re-encoded message.
+fn round_trip<T>(json: &Value) -> Value
+    where T: Serialize + DeserializeOwned
+{
+    let decoded: T = serde_json::from_value(json.clone())
+        .expect("Could not decode message");
+    let encoded = serde_json::to_value(&decoded).unwrap();
+    let decoded_again: T = serde_json::from_value(encoded.clone())
+        .expect("Could not decode re-encoded message");
+    assert_eq!(encoded, serde_json::to_value(&decoded_again).unwrap());
+    encoded
+}
+
+fn without_state(msg: &Value) -> Value {
+    let mut msg = msg.clone();
+    if let Some(fields) = msg.as_object_mut() {
+        fields.remove("state");
+    }
+    msg
+}
+
+fn state(setup: &str) -> Value {
+    let input: Input = serde_json::from_str(setup).unwrap();
+    serde_json::to_value(Punter::new(input, PunterType::Random)).unwrap()
+}
+
+fn with_state(msg: &Value, state: &Value) -> Value {
+    let mut msg = msg.clone();
+    msg.as_object_mut().unwrap().insert("state".to_string(), state.clone());
+    msg
+}
+
+#[test]
+fn handshake() {
+    let handshake = parse(HANDSHAKE);
+    assert_eq!(round_trip::<protocol::HandshakeP>(&handshake["punter"]),
+               handshake["punter"]);
+    assert_eq!(round_trip::<protocol::HandshakeS>(&handshake["server"]),
+               handshake["server"]);
+    assert_eq!(serde_json::to_value(punter::punter::handshake("random hackers".to_string())).unwrap(),
+               handshake["punter"]);
+}
+
+#[test]
+fn setup_without_settings() {
+    let setup = parse(SETUP);
+    let encoded = round_trip::<Input>(&setup);
+    assert_eq!(encoded["punter"], setup["punter"]);
+    assert_eq!(encoded["punters"], setup["punters"]);
+    assert_eq!(encoded["map"]["mines"], setup["map"]["mines"]);
+    let sites = encoded["map"]["sites"].as_array().unwrap();
+    for (site, expected) in sites.iter().zip(setup["map"]["sites"].as_array().unwrap()) {
+        assert_eq!(site["id"], expected["id"]);
+        assert_eq!(site["x"], expected["x"]);
+        assert_eq!(site["y"], expected["y"]);
+    }
+    let rivers = encoded["map"]["rivers"].as_array().unwrap();
+    assert_eq!(rivers.len(), 12);
+    for (river, expected) in rivers.iter().zip(setup["map"]["rivers"].as_array().unwrap()) {
+        assert_eq!(river["source"], expected["source"]);
+        assert_eq!(river["target"], expected["target"]);
+        assert_eq!(river["owner"], Value::Null);
+    }
+    for setting in &["futures", "splurges", "options"] {
+        assert_eq!(encoded["settings"][setting], Value::Bool(false));
+    }
+}
+
+#[test]
+fn setup_with_settings() {
+    let setup = parse(SETUP_SETTINGS);
+    let encoded = round_trip::<Input>(&setup);
+    assert_eq!(encoded["punters"], setup["punters"]);
+    for setting in &["futures", "splurges", "options"] {
+        assert_eq!(encoded["settings"][setting], Value::Bool(true));
+    }
+}
+
+#[test]
+fn ready() {
+    let ready = parse(r#"{"ready": 1}"#);
+    assert_eq!(round_trip::<protocol::ReadyP>(&ready), ready);
+}
+
+#[test]
+fn turns() {
+    for turn in parse(TURNS).as_array().unwrap() {
+        assert_eq!(&round_trip::<protocol::TurnS>(turn), turn);
+    }
+}
+
+#[test]
+fn turn_variants() {
+    let turns: Vec<protocol::TurnS> = serde_json::from_str(TURNS).unwrap();
+    match turns[2] {
+        protocol::TurnS::turn { ref moves } => {
+            match moves[0] {
+                protocol::Move::splurge (ref splurge) => {
+                    assert_eq!(splurge.punter, 0);
+                    assert_eq!(splurge.route, vec![0, 1, 2]);
+                }
+                ref m => panic!("Expected splurge, got {:?}", m),
+            }
+            match moves[1] {
+                protocol::Move::option (ref option) => {
+                    assert_eq!((option.punter, option.source, option.target), (1, 3, 5));
+                }
+                ref m => panic!("Expected option, got {:?}", m),
+            }
+        }
+        ref t => panic!("Expected move, got {:?}", t),
+    }
+    match turns[3] {
+        protocol::TurnS::timeout (timeout) => assert_eq!(timeout, 1.0),
+        ref t => panic!("Expected timeout, got {:?}", t),
+    }
+    match turns[4] {
+        protocol::TurnS::stop { ref moves, ref scores } => {
+            assert_eq!(moves.len(), 2);
+            assert_eq!(scores.len(), 2);
+        }
+        ref t => panic!("Expected stop, got {:?}", t),
+    }
+}
+
+#[test]
+fn moves() {
+    for turn in parse(TURNS).as_array().unwrap() {
+        if let Some(moves) = turn["move"]["moves"].as_array() {
+            for m in moves {
+                assert_eq!(&round_trip::<protocol::Move>(m), m);
+            }
+        }
+    }
+}
+
+#[test]
+fn offline_input() {
+    let state = state(SETUP);
+    let turns = parse(TURNS);
+
+    match serde_json::from_str::<protocol::OfflineInput>(SETUP).unwrap() {
+        protocol::OfflineInput::Setup (_) => {}
+        i => panic!("Expected setup, got {:?}", i),
+    }
+    match serde_json::from_value::<protocol::OfflineInput>(with_state(&turns[1], &state)).unwrap() {
+        protocol::OfflineInput::Turn (turn) => {
+            assert_eq!(turn.turn.moves.len(), 2);
+            assert_eq!(turn.state.id(), 1);
+        }
+        i => panic!("Expected turn, got {:?}", i),
+    }
+    match serde_json::from_value::<protocol::OfflineInput>(with_state(&turns[4], &state)).unwrap() {
+        protocol::OfflineInput::Stop (stop) => {
+            assert_eq!(stop.stop.moves.len(), 2);
+            assert_eq!(stop.stop.scores.len(), 2);
+        }
+        i => panic!("Expected stop, got {:?}", i),
+    }
+    match serde_json::from_value::<protocol::OfflineInput>(turns[3].clone()).unwrap() {
+        protocol::OfflineInput::Timeout { timeout } => assert_eq!(timeout, 1.0),
+        i => panic!("Expected timeout, got {:?}", i),
+    }
+
+    for turn in turns.as_array().unwrap() {
+        let msg = match turn.get("timeout") {
+            Some(_) => turn.clone(),
+            None => with_state(turn, &state),
+        };
+        round_trip::<protocol::OfflineInput>(&msg);
+    }
+}
+
+#[test]
+fn offline_move() {
+    let state = state(SETUP);
+    for turn in parse(TURNS).as_array().unwrap() {
+        if let Some(moves) = turn["move"]["moves"].as_array() {
+            for m in moves {
+                let msg = with_state(m, &state);
+                let encoded = round_trip::<protocol::OfflineMove>(&msg);
+                let kind = m.as_object().unwrap().keys().next().unwrap();
+                assert_eq!(encoded[kind], m[kind]);
+                let parsed: protocol::OfflineMove = serde_json::from_value(msg).unwrap();
+                match (kind.as_str(), parsed) {
+                    ("claim", protocol::OfflineMove::Claim (_)) |
+                    ("pass", protocol::OfflineMove::Pass (_)) |
+                    ("splurge", protocol::OfflineMove::Splurge (_)) |
+                    ("option", protocol::OfflineMove::Option (_)) => {}
+                    (kind, parsed) => panic!("Expected {}, got {:?}", kind, parsed),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn offline_ready() {
+    let state = state(SETUP);
+    let ready = with_state(&parse(r#"{"ready": 1}"#), &state);
+    assert_eq!(without_state(&round_trip::<protocol::OfflineReadyP>(&ready)),
+               without_state(&ready));
+}
+
+fn offline_error(msg: &Value) -> String {
+    serde_json::from_value::<protocol::OfflineInput>(msg.clone())
+        .expect_err("Broken message was accepted")
+        .to_string()
+}
+
+#[test]
+fn offline_input_errors() {
+    let state = state(SETUP);
+    let turns = parse(TURNS);
+
+    let mut turn = with_state(&turns[1], &state);
+    turn["move"]["moves"][0]["claim"]["source"] = Value::from("three");
+    assert!(offline_error(&turn).contains("invalid field `move` in offline move message"));
+
+    assert!(offline_error(&turns[1]).contains("missing field `state` in offline move message"));
+    assert!(offline_error(&turns[4]).contains("missing field `state` in offline stop message"));
+
+    let mut setup = parse(SETUP);
+    setup["punters"] = Value::from(-2);
+    assert!(offline_error(&setup).contains("invalid field `punters` in offline setup message"));
+
+    let mut setup = parse(SETUP);
+    setup["map"]["mines"] = Value::Null;
+    assert!(offline_error(&setup).contains("invalid field `map` in offline setup message"));
+
+    let mut setup = parse(SETUP_SETTINGS);
+    setup["settings"]["futures"] = Value::from("yes");
+    assert!(offline_error(&setup).contains("invalid field `settings` in offline setup message"));
+
+    let mut setup = parse(SETUP);
+    setup.as_object_mut().unwrap().remove("punter");
+    assert!(offline_error(&setup).contains("missing field `punter` in offline setup message"));
+
+    assert!(offline_error(&parse(r#"{"hello": 1}"#)).contains("unrecognized offline message"));
+}
+
+#[test]
+fn offline_input_unknown_fields() {
+    let state = state(SETUP);
+    let turns = parse(TURNS);
+
+    let mut setup = parse(SETUP_SETTINGS);
+    setup["lobby"] = Value::from("map-7");
+    setup["settings"]["handicap"] = Value::Bool(true);
+    match serde_json::from_value::<protocol::OfflineInput>(setup).unwrap() {
+        protocol::OfflineInput::Setup (_) => {}
+        i => panic!("Expected setup, got {:?}", i),
+    }
+
+    let mut turn = with_state(&turns[1], &state);
+    turn["move"]["elapsed"] = Value::from(12);
+    turn["server_version"] = Value::from(2);
+    match serde_json::from_value::<protocol::OfflineInput>(turn).unwrap() {
+        protocol::OfflineInput::Turn (_) => {}
+        i => panic!("Expected turn, got {:?}", i),
+    }
+
+    let mut timeout = turns[3].clone();
+    timeout["punter"] = Value::from(1);
+    match serde_json::from_value::<protocol::OfflineInput>(timeout).unwrap() {
+        protocol::OfflineInput::Timeout { timeout } => assert_eq!(timeout, 1.0),
+        i => panic!("Expected timeout, got {:?}", i),
+    }
+}
+
+#[test]
+fn offline_state_keeps_map_extras() {
+    let mut setup = parse(SETUP);
+    setup["map"]["name"] = Value::from("sample");
+    setup["map"]["sites"][0]["label"] = Value::from("Edinburgh");
+    let input: Input = serde_json::from_value(setup.clone()).unwrap();
+    let state = serde_json::to_value(Punter::new(input, PunterType::Random)).unwrap();
+    let punter: Punter = serde_json::from_value(state.clone()).unwrap();
+
+    assert_eq!(punter.map_field("name"), Some(&Value::from("sample")));
+    assert_eq!(punter.site_field(4, "label"), Some(&Value::from("Edinburgh")));
+    assert_eq!(punter.site_field(0, "label"), None);
+    assert_eq!(punter.site_coordinates(4), Some((2.0, -2.0)));
+    assert_eq!(punter.site_coordinates(42), None);
+
+    // A second trip through the state loses nothing either
+    let again = serde_json::to_value(&punter).unwrap();
+    assert_eq!(again["game"]["map"], state["game"]["map"]);
+    assert_eq!(again["game"]["map"]["name"], "sample");
+    assert_eq!(again["game"]["map"]["sites"][0]["x"], setup["map"]["sites"][0]["x"]);
+}
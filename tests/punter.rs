@@ -0,0 +1,590 @@
+// Game-state tests for the punter library, driven through its public API.
+extern crate punter;
+extern crate serde_json;
+
+use std::time::{Duration, Instant};
+
+use punter::protocol;
+use punter::punter::{Diagnostics, Input, Punter, PunterType, ScoreMismatch, TimeBudget, TreeFormat};
+
+mod common;
+use common::{claim, option, pass, punter_on, sample_punter, square_punter};
+
+#[test]
+fn map_hash_tells_maps_apart() {
+    let sample = sample_punter(PunterType::Random).map_hash();
+    assert_eq!(sample_punter(PunterType::Greedy).map_hash(), sample);
+    assert_ne!(square_punter().map_hash(), sample);
+}
+
+#[test]
+fn state_reserve_grows_with_the_map() {
+    let sample = sample_punter(PunterType::Random).state_reserve();
+    assert!(sample > Duration::from_secs(0) && sample < Duration::from_millis(1));
+    assert!(square_punter().state_reserve() < sample);
+}
+
+#[test]
+fn scores() {
+    let mut punter = sample_punter(PunterType::Random);
+    assert_eq!(punter.scores(), vec![0, 0]);
+
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 1, 3)]);
+    punter.process_turn(&[claim(0, 5, 7), claim(1, 0, 1)]);
+    assert_eq!(punter.scores(), vec![2, 2]);
+}
+
+#[test]
+fn score_mismatches() {
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 1, 3)]);
+
+    let agree = vec![
+        protocol::Score { punter: 0, score: 1 },
+        protocol::Score { punter: 1, score: 1 },
+    ];
+    assert_eq!(punter.score_mismatches(&agree), vec![]);
+
+    let disagree = vec![
+        protocol::Score { punter: 0, score: 1 },
+        protocol::Score { punter: 1, score: 5 },
+        protocol::Score { punter: 7, score: 0 },
+    ];
+    assert_eq!(punter.score_mismatches(&disagree), vec![
+        ScoreMismatch { punter: 1, computed: Some(1), reported: 5 },
+        ScoreMismatch { punter: 7, computed: None, reported: 0 },
+    ]);
+}
+
+#[test]
+fn score_breakdown_without_settings() {
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 1, 3)]);
+    punter.process_turn(&[claim(0, 5, 7), claim(1, 0, 1)]);
+
+    let breakdown = punter.score_breakdown();
+    assert_eq!(breakdown.len(), 2);
+    assert_eq!(breakdown[0].total, 2);
+    assert_eq!(breakdown[0].mines[0].mine, 1);
+    assert_eq!(breakdown[0].mines[0].score, 0);
+    assert_eq!(breakdown[0].mines[0].reachable, vec![1]);
+    assert_eq!(breakdown[0].mines[1].mine, 5);
+    assert_eq!(breakdown[0].mines[1].score, 2);
+    let mut reachable = breakdown[0].mines[1].reachable.clone();
+    reachable.sort();
+    assert_eq!(reachable, vec![3, 5, 7]);
+    assert!(breakdown[0].futures.is_none());
+    assert!(breakdown[0].options.is_none());
+    assert_eq!(breakdown[1].total, 2);
+}
+
+#[test]
+fn score_breakdown_with_futures_and_options() {
+    let mut punter = square_punter();
+    punter.set_futures(0, vec![
+        protocol::Future { source: 0, target: 1 },
+        protocol::Future { source: 2, target: 3 },
+    ]).unwrap();
+    punter.process_turn(&[
+        claim(0, 0, 1),
+        claim(1, 2, 3),
+        option(2, 0, 1),
+    ]);
+    punter.process_turn(&[claim(0, 1, 2)]);
+
+    let breakdown = punter.score_breakdown();
+    assert_eq!(punter.scores(), vec![10, 1, 1]);
+    assert_eq!(breakdown.iter().map(|b| b.total).collect::<Vec<_>>(), punter.scores());
+
+    let ours = &breakdown[0];
+    assert_eq!(ours.mines.iter().map(|m| m.score).collect::<Vec<_>>(), vec![5, 5]);
+    let ours_futures = ours.futures.as_ref().unwrap();
+    assert_eq!(ours_futures.len(), 2);
+    assert!(ours_futures[0].fulfilled);
+    assert_eq!(ours_futures[0].score, 1);
+    assert!(!ours_futures[1].fulfilled);
+    assert_eq!(ours_futures[1].score, -1);
+    assert_eq!(ours.total, 10);
+    assert_eq!(ours.options.as_ref().unwrap().used, 0);
+
+    assert_eq!(breakdown[1].total, 1);
+    assert_eq!(breakdown[1].futures.as_ref().unwrap().len(), 0);
+
+    let renter = &breakdown[2];
+    assert_eq!(renter.total, 1);
+    assert_eq!(renter.options.as_ref().unwrap().used, 1);
+    assert_eq!(renter.options.as_ref().unwrap().available, 2);
+
+    let json = serde_json::to_value(&breakdown).unwrap();
+    assert_eq!(json[0]["futures"][0]["score"], 1);
+    assert_eq!(json[2]["options"]["used"], 1);
+}
+
+#[test]
+fn opponent_models() {
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 0, 1)]);
+    punter.process_turn(&[claim(0, 5, 7), claim(1, 2, 3)]);
+    punter.process_turn(&[pass(0), claim(1, 4, 5)]);
+    assert_eq!(punter.turn(), 3);
+    // Passes use up the game's moves like claims do
+    assert_eq!(punter.moves_made(), 6);
+    assert_eq!(punter.moves_left(), punter.num_rivers() - 6);
+
+    let history = punter.history();
+    assert_eq!(history.len(), 6);
+    assert_eq!((history[0].turn, history[0].punter), (1, 0));
+    assert_eq!(history[0].rivers.len(), 1);
+    assert_eq!((history[4].turn, history[4].punter, history[4].rivers.len()), (3, 0, 0));
+
+    let greedy = punter.opponent(0).unwrap();
+    assert!(greedy.is_informed());
+    assert_eq!(greedy.moves, 3);
+    assert_eq!(greedy.passes, 1);
+    assert_eq!(greedy.connected_claims, 2);
+    assert_eq!(greedy.mine_claims, vec![0, 2]);
+    assert_eq!(greedy.favourite_mine(), Some(1));
+    assert_eq!(greedy.greediness(), 1.);
+    assert!((greedy.pass_rate() - 1. / 3.).abs() < 1e-9);
+    assert_eq!(greedy.last_turn, Some(3));
+
+    let scattered = punter.opponent(1).unwrap();
+    assert_eq!(scattered.passes, 0);
+    assert_eq!(scattered.connected_claims, 2);
+    assert_eq!(scattered.mine_claims, vec![1, 1]);
+    assert!((scattered.greediness() - 2. / 3.).abs() < 1e-9);
+
+    assert!(punter.opponent(2).is_none());
+}
+
+#[test]
+fn opponent_models_survive_serialization() {
+    let mut punter = sample_punter(PunterType::Random);
+    punter.process_turn(&[claim(0, 3, 5), pass(1)]);
+    punter.process_turn(&[pass(0), claim(1, 1, 3)]);
+    let state = serde_json::to_string(&punter).unwrap();
+    let restored: Punter = serde_json::from_str(&state).unwrap();
+    assert_eq!(restored.turn(), 2);
+    assert_eq!(restored.history(), punter.history());
+    assert_eq!(restored.opponent(0).unwrap().passes, 1);
+}
+
+#[test]
+fn splurges_are_observed_as_one_move() {
+    let input: Input = serde_json::from_str(r#"{"punter": 0, "punters": 3, "map": {
+        "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}],
+        "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+                   {"source": 2, "target": 3}, {"source": 3, "target": 4},
+                   {"source": 4, "target": 5}, {"source": 5, "target": 0},
+                   {"source": 0, "target": 3}, {"source": 1, "target": 4}],
+        "mines": [0, 3]},
+        "settings": {"splurges": true}}"#).unwrap();
+    let mut punter = Punter::new(input, PunterType::Random);
+    punter.process_turn(&[pass(0), pass(1), pass(2)]);
+    punter.process_turn(&[claim(0, 0, 1), pass(1), pass(2)]);
+    punter.process_turn(&[pass(0), claim(1, 3, 4),
+                          protocol::Move::splurge (protocol::Splurge { punter: 2, route: vec![1, 2, 3] })]);
+    let splurge = punter.history().last().unwrap();
+    assert_eq!((splurge.punter, splurge.rivers.len()), (2, 2));
+    let splurger = punter.opponent(2).unwrap();
+    assert_eq!((splurger.moves, splurger.passes), (2, 1));
+    // The route starts from nothing of its own, but ends up at mine 3
+    assert_eq!(splurger.connected_claims, 0);
+    assert_eq!(splurger.mine_claims, vec![0, 1]);
+}
+
+// Four rivers in a row from the mine at 0, so the game ends with our move
+const PATH: &str = r#"{"punter": 1, "punters": 2, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+               {"source": 2, "target": 3}, {"source": 3, "target": 4}],
+    "mines": [0]}}"#;
+
+#[test]
+fn stop_adds_only_new_moves() {
+    let input: Input = serde_json::from_str(PATH).unwrap();
+    let mut punter = Punter::new(input, PunterType::Random);
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    punter.process_turn(&[pass(0), claim(1, 1, 2)]);
+    // Punter 0's pass was in the last turn already; our claim is new
+    punter.process_stop(&[pass(0), claim(1, 2, 3)]);
+    assert_eq!(punter.river_owner(2), Some(1));
+    assert_eq!(punter.opponent(0).unwrap().passes, 1);
+    assert_eq!(punter.opponent(1).unwrap().moves, 2);
+    assert_eq!(punter.moves_left(), 0);
+}
+
+fn claimed(m: protocol::Move) -> (usize, usize, usize) {
+    match m {
+        protocol::Move::claim (c) => (c.punter, c.source, c.target),
+        m => panic!("Expected claim, got {:?}", m),
+    }
+}
+
+#[test]
+fn ai_names() {
+    assert_eq!("greedy".parse::<PunterType>(), Ok(PunterType::Greedy));
+    assert_eq!("connect-mines".parse::<PunterType>(), Ok(PunterType::ConnectMines));
+    assert_eq!("block-leader".parse::<PunterType>(), Ok(PunterType::BlockLeader));
+    assert_eq!("mcts".parse::<PunterType>(), Ok(PunterType::MCTS));
+    assert_eq!("random".parse::<PunterType>(), Ok(PunterType::Random));
+    assert_eq!("steiner".parse::<PunterType>(), Ok(PunterType::Steiner));
+    assert!("smart".parse::<PunterType>().is_err());
+}
+
+#[test]
+fn greedy_takes_best_immediate_gain() {
+    let mut punter = sample_punter(PunterType::Greedy);
+    // We own 1-3; joining mine 5 scores for both mines
+    punter.process_turn(&[claim(0, 6, 7), claim(1, 1, 3)]);
+    let (who, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    assert_eq!(who, 1);
+    assert_eq!((source, target), (3, 5));
+}
+
+#[test]
+fn connect_mines_heads_for_the_other_mine() {
+    let mut punter = sample_punter(PunterType::ConnectMines);
+    punter.process_turn(&[claim(0, 3, 5), claim(1, 0, 1)]);
+    let (_, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    // 3-5 is taken, so the shortest way from our network to mine 5 is via 7
+    assert!(source == 0 || source == 1);
+    assert_eq!(target, 7);
+}
+
+#[test]
+fn block_leader_takes_leaders_best_river() {
+    let mut punter = sample_punter(PunterType::BlockLeader);
+    punter.process_turn(&[claim(0, 3, 5), pass(1)]);
+    let (who, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    assert_eq!(who, 1);
+    assert_eq!((source, target), (1, 3));
+}
+
+#[test]
+fn every_ai_passes_when_nothing_is_left() {
+    let all_rivers = vec![
+        claim(0, 3, 4), claim(0, 0, 1), claim(0, 2, 3), claim(0, 1, 3),
+        claim(0, 5, 6), claim(0, 4, 5), claim(0, 3, 5), claim(0, 6, 7),
+        claim(0, 5, 7), claim(0, 1, 7), claim(0, 0, 7), claim(0, 1, 2),
+    ];
+    for ai in &[PunterType::Random, PunterType::MCTS, PunterType::Greedy,
+                PunterType::ConnectMines, PunterType::BlockLeader] {
+        let mut punter = sample_punter(*ai);
+        punter.process_turn(&all_rivers);
+        match punter.make_move(Instant::now(), Duration::from_millis(50)) {
+            protocol::Move::pass (p) => assert_eq!(p.punter, 1),
+            m => panic!("{:?} should have passed, got {:?}", ai, m),
+        }
+    }
+}
+
+#[test]
+fn mcts_without_time_falls_back_to_greedy() {
+    let mut punter = sample_punter(PunterType::MCTS);
+    punter.process_turn(&[claim(0, 6, 7), claim(1, 1, 3)]);
+    let (_, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    assert_eq!((source, target), (3, 5));
+}
+
+#[test]
+fn mcts_search_variants() {
+    for &(transpositions, rave) in &[(true, 300.), (false, 300.), (true, 0.), (false, 0.)] {
+        let mut punter = sample_punter(PunterType::MCTS);
+        let mut config = punter.config().clone();
+        config.transpositions = transpositions;
+        config.rave_equivalence = rave;
+        config.endgame_threshold = 0;
+        punter.set_config(config);
+        punter.process_turn(&[claim(0, 6, 7), claim(1, 1, 3)]);
+        let (who, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_millis(200)));
+        assert_eq!(who, 1);
+        // Only free rivers can be claimed
+        assert!((source, target) != (6, 7) && (source, target) != (1, 3));
+    }
+}
+
+#[test]
+fn search_diagnostics() {
+    let mut punter = sample_punter(PunterType::MCTS);
+    let mut config = punter.config().clone();
+    config.endgame_threshold = 0;
+    punter.set_config(config);
+    punter.process_turn(&[claim(0, 6, 7), claim(1, 1, 3)]);
+
+    let diagnostics = Diagnostics { top_k: 3, tree: Some(TreeFormat::Json) };
+    let (_, stats) = punter.make_move_diagnosed(Instant::now(), Duration::from_millis(200), &diagnostics);
+    let stats = stats.unwrap();
+    assert!(stats.iterations > 0);
+    assert!(stats.depth >= 1);
+    assert!(stats.nodes > 1);
+    assert_eq!(stats.children.len(), 3);
+    assert!(stats.children.windows(2).all(|pair| pair[0].visits >= pair[1].visits));
+    let tree: serde_json::Value = serde_json::from_str(stats.tree.as_ref().unwrap()).unwrap();
+    assert_eq!(tree["nodes"].as_array().unwrap().len(), stats.nodes);
+    assert!(tree["edges"].as_array().unwrap().len() >= stats.nodes - 1);
+
+    let diagnostics = Diagnostics { top_k: 1, tree: Some(TreeFormat::Dot) };
+    let (_, stats) = punter.make_move_diagnosed(Instant::now(), Duration::from_millis(50), &diagnostics);
+    let stats = stats.unwrap();
+    assert_eq!(stats.children.len(), 1);
+    assert!(stats.tree.unwrap().starts_with("digraph mcts {"));
+
+    // Only tree searches have anything to report
+    let greedy = sample_punter(PunterType::Greedy);
+    assert!(greedy.make_move_diagnosed(Instant::now(), Duration::from_secs(0), &diagnostics).1.is_none());
+}
+
+#[test]
+fn mcts_node_budget() {
+    let mut punter = sample_punter(PunterType::MCTS);
+    let mut config = punter.config().clone();
+    config.endgame_threshold = 0;
+    config.max_nodes = 20;
+    punter.set_config(config);
+    punter.process_turn(&[claim(0, 6, 7), claim(1, 1, 3)]);
+
+    let diagnostics = Diagnostics { top_k: 3, tree: Some(TreeFormat::Json) };
+    let (next_move, stats) = punter.make_move_diagnosed(Instant::now(), Duration::from_millis(200), &diagnostics);
+    let stats = stats.unwrap();
+    // Pruning kicks in as soon as the tree outgrows the budget
+    assert_eq!(stats.peak_nodes, 21);
+    assert!(stats.nodes <= 21);
+    let tree: serde_json::Value = serde_json::from_str(stats.tree.as_ref().unwrap()).unwrap();
+    assert_eq!(tree["nodes"].as_array().unwrap().len(), stats.nodes);
+    let (_, source, target) = claimed(next_move);
+    assert!((source, target) != (6, 7) && (source, target) != (1, 3));
+}
+
+// A mine with one river, which punter 0 takes, and a path of rivers on an
+// island with no mine, which is too far away for the search to consider
+const FAR_AWAY: &str = r#"{"punter": 1, "punters": 2, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 2, "target": 3},
+               {"source": 3, "target": 4}, {"source": 4, "target": 5}],
+    "mines": [0]}}"#;
+
+// Like FAR_AWAY, but with 1-2 next to the mine for the search to consider
+const ONE_NEARBY: &str = r#"{"punter": 1, "punters": 2, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+               {"source": 3, "target": 4}, {"source": 4, "target": 5}],
+    "mines": [0]}}"#;
+
+fn mcts_on(map: &str) -> Punter {
+    let mut punter = punter_on(map, PunterType::MCTS);
+    let mut config = punter.config().clone();
+    config.endgame_threshold = 0;
+    punter.set_config(config);
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    punter
+}
+
+#[test]
+fn rollouts_go_on_past_the_search_radius() {
+    let punter = mcts_on(FAR_AWAY);
+    let diagnostics = Diagnostics { top_k: 3, tree: None };
+    let (_, stats) = punter.make_move_diagnosed(Instant::now(), Duration::from_millis(50), &diagnostics);
+    let stats = stats.unwrap();
+    // Nothing for the tree to branch on, yet every rollout plays all three
+    // moves that are left
+    assert!(stats.iterations > 0);
+    assert_eq!(stats.nodes, 1);
+    assert_eq!(stats.playout_claims, 3.);
+}
+
+#[test]
+fn unexpandable_leaves_are_played_out() {
+    let punter = mcts_on(ONE_NEARBY);
+    let diagnostics = Diagnostics { top_k: 3, tree: None };
+    let (_, stats) = punter.make_move_diagnosed(Instant::now(), Duration::from_millis(50), &diagnostics);
+    let stats = stats.unwrap();
+    // The tree only gets as far as 1-2; the island is left to the rollouts
+    assert_eq!(stats.depth, 1);
+    assert_eq!(stats.nodes, 2);
+    assert_eq!(stats.playout_claims, 3.);
+}
+
+// A line of five sites with mines at 0 and 3, where punter 0 already holds
+// 0-1 and 2-3 and two rivers are left, as well as two moves. Taking 3-4
+// scores right away but lets punter 0 link both its rivers through 1-2;
+// blocking that is worth more. We hold 5-6, off on an island.
+const LINE: &str = r#"{"punter": 1, "punters": 2, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}, {"id": 6}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+               {"source": 2, "target": 3}, {"source": 3, "target": 4},
+               {"source": 5, "target": 6}],
+    "mines": [0, 3]}}"#;
+
+#[test]
+fn endgame_solver_blocks_instead_of_scoring() {
+    let input: Input = serde_json::from_str(LINE).unwrap();
+    let mut punter = Punter::new(input, PunterType::MCTS);
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    punter.process_turn(&[claim(0, 2, 3), claim(1, 5, 6)]);
+    assert_eq!(punter.moves_left(), 2);
+    let (_, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(1)));
+    assert_eq!((source, target), (1, 2));
+
+    // Greedy (which MCTS falls back to without time) goes for the points
+    let (_, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    assert_eq!((source, target), (3, 4));
+}
+
+// Ids are huge, out of order and far apart
+const SPARSE: &str = r#"{"punter": 0, "punters": 2, "map": {
+    "sites": [{"id": 1125899906842624}, {"id": 7}, {"id": 1099511627776}],
+    "rivers": [{"source": 7, "target": 1099511627776}, {"source": 1125899906842624, "target": 7}],
+    "mines": [1099511627776]}}"#;
+
+#[test]
+fn sparse_site_ids() {
+    let input: Input = serde_json::from_str(SPARSE).unwrap();
+    let mut punter = Punter::new(input, PunterType::Random);
+    punter.process_turn(&[claim(0, 1099511627776, 7)]);
+    punter.process_turn(&[claim(0, 7, 1125899906842624)]);
+    assert_eq!(punter.river_owner(0), Some(0));
+    assert_eq!(punter.river_owner(1), Some(0));
+    assert_eq!(punter.scores(), vec![1 + 4, 0]);
+
+    let state = serde_json::to_string(&punter).unwrap();
+    let punter: Punter = serde_json::from_str(&state).unwrap();
+    assert_eq!(punter.scores(), vec![1 + 4, 0]);
+}
+
+// Two islands and a lone mine
+const ISLANDS: &str = r#"{"punter": 1, "punters": 2, "map": {
+    "sites": [{"id": 0}, {"id": 1}, {"id": 2}, {"id": 3}, {"id": 4}, {"id": 5}, {"id": 6}],
+    "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2},
+               {"source": 3, "target": 4}, {"source": 4, "target": 5}],
+    "mines": [0, 3, 6]},
+    "settings": {"futures": true}}"#;
+
+fn islands(ai: PunterType) -> Punter {
+    punter_on(ISLANDS, ai)
+}
+
+// The same islands with 0-1 doubled up
+fn doubled_islands(ai: PunterType) -> Punter {
+    let mut input: serde_json::Value = serde_json::from_str(ISLANDS).unwrap();
+    input["map"]["rivers"].as_array_mut().unwrap()
+        .insert(2, serde_json::from_str(r#"{"source": 1, "target": 0}"#).unwrap());
+    Punter::new(serde_json::from_value(input).unwrap(), ai)
+}
+
+// The opponent takes 0-1 and leaves the rest of the game to us. Returns
+// how many rivers we own at the end.
+fn play_out(punter: &mut Punter) -> usize {
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    while punter.moves_left() > 0 {
+        let next = punter.make_move(Instant::now(), Duration::from_millis(20));
+        punter.process_turn(&[next]);
+    }
+    (0..punter.num_rivers())
+        .filter(|r| punter.river_owner(*r) == Some(1))
+        .count()
+}
+
+#[test]
+fn disconnected_map_scores() {
+    let mut punter = islands(PunterType::Random);
+    assert_eq!(punter.scores(), vec![0, 0]);
+    punter.process_turn(&[claim(0, 0, 1), claim(1, 3, 4)]);
+    punter.process_turn(&[claim(0, 1, 2), claim(1, 4, 5)]);
+    // Nothing reaches across the islands or out of the lone mine
+    assert_eq!(punter.scores(), vec![1 + 4, 1 + 4]);
+
+    let mut punter = islands(PunterType::Random);
+    punter.set_futures(0, vec![
+        protocol::Future { source: 0, target: 2 },
+        protocol::Future { source: 0, target: 5 },
+        protocol::Future { source: 6, target: 4 },
+    ]).unwrap();
+    punter.process_turn(&[claim(0, 0, 1), claim(1, 3, 4)]);
+    punter.process_turn(&[claim(0, 1, 2), claim(1, 4, 5)]);
+    let ours = &punter.score_breakdown()[0];
+    let scores = ours.futures.as_ref().unwrap().iter()
+        .map(|f| (f.fulfilled, f.score))
+        .collect::<Vec<_>>();
+    // Futures on unreachable targets are worth nothing either way
+    assert_eq!(scores, vec![(true, 8), (false, 0), (false, 0)]);
+    assert_eq!(ours.mines.iter().map(|m| m.reachable.len()).collect::<Vec<_>>(), vec![3, 1, 1]);
+    assert_eq!(ours.total, 5 + 8);
+}
+
+#[test]
+fn every_ai_plays_out_a_disconnected_map() {
+    for ai in &[PunterType::Random, PunterType::MCTS, PunterType::Greedy,
+                PunterType::ConnectMines, PunterType::BlockLeader, PunterType::Steiner] {
+        let mut punter = islands(*ai);
+        assert_eq!(play_out(&mut punter), 3, "{:?}", ai);
+        assert!(punter.scores()[1] > 0);
+    }
+}
+
+#[test]
+fn every_ai_plays_out_parallel_rivers() {
+    for ai in &[PunterType::Random, PunterType::MCTS, PunterType::Greedy,
+                PunterType::ConnectMines, PunterType::BlockLeader, PunterType::Steiner] {
+        let mut punter = doubled_islands(*ai);
+        // We get the other copy of 0-1 as well
+        assert_eq!(play_out(&mut punter), 4, "{:?}", ai);
+        assert!(punter.scores()[1] > 0);
+    }
+}
+
+#[test]
+fn duplicate_rivers_score_once() {
+    let input: Input = serde_json::from_str(r#"{"punter": 0, "punters": 2, "map": {
+        "sites": [{"id": 0}, {"id": 1}, {"id": 2}],
+        "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 0}, {"source": 1, "target": 2}],
+        "mines": [0]}}"#).unwrap();
+    let mut punter = Punter::new(input, PunterType::Greedy);
+    punter.process_turn(&[claim(0, 0, 1), pass(1)]);
+    assert_eq!(punter.scores(), vec![1, 0]);
+    // The copy of 0-1 adds nothing to what we have
+    let (_, source, target) = claimed(punter.make_move(Instant::now(), Duration::from_secs(0)));
+    assert_eq!((source, target), (1, 2));
+}
+
+#[test]
+fn parallel_rivers_are_claimed_and_optioned_separately() {
+    let input: Input = serde_json::from_str(r#"{"punter": 0, "punters": 3, "map": {
+        "sites": [{"id": 0}, {"id": 1}, {"id": 2}],
+        "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 0},
+                   {"source": 0, "target": 1}, {"source": 1, "target": 2},
+                   {"source": 1, "target": 2}, {"source": 1, "target": 2}],
+        "mines": [0]},
+        "settings": {"options": true}}"#).unwrap();
+    let mut punter = Punter::new(input, PunterType::Random);
+    punter.process_turn(&[claim(0, 0, 1), claim(1, 1, 0), option(2, 0, 1)]);
+    assert_eq!((0..4).map(|r| punter.river_owner(r)).collect::<Vec<_>>(),
+               vec![Some(0), Some(1), None, None]);
+    assert_eq!(punter.scores(), vec![1, 1, 1]);
+
+    // Our option can only go on the copy punter 1 owns, which nobody rents
+    punter.process_turn(&[option(0, 1, 0), claim(1, 0, 1), claim(2, 2, 1)]);
+    assert_eq!((0..4).map(|r| punter.river_owner(r)).collect::<Vec<_>>(),
+               vec![Some(0), Some(1), Some(1), Some(2)]);
+    let used = punter.score_breakdown().iter()
+        .map(|b| b.options.as_ref().unwrap().used)
+        .collect::<Vec<_>>();
+    assert_eq!(used, vec![1, 0, 1]);
+    assert_eq!(punter.scores(), vec![1, 1, 1 + 4]);
+}
+
+#[test]
+fn time_budget_backs_off() {
+    let mut budget = TimeBudget::new(1);
+    assert_eq!(budget.duration(), Duration::from_millis(900));
+    budget.timed_out();
+    assert_eq!(budget.duration(), Duration::from_millis(800));
+    budget.timed_out();
+    assert_eq!(budget.duration(), Duration::from_millis(600));
+    budget.timed_out();
+    assert_eq!(budget.duration(), Duration::from_millis(500));
+    budget.timed_out();
+    assert_eq!(budget.duration(), Duration::from_millis(500));
+
+    assert_eq!(TimeBudget::new(0).duration(), Duration::from_millis(0));
+}
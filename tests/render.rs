@@ -0,0 +1,74 @@
+// SVG rendering of the sample map, with and without coordinates.
+extern crate punter;
+extern crate serde_json;
+
+use punter::protocol::GameRecord;
+use punter::punter::Input;
+use punter::render;
+
+mod common;
+use common::{claim, option, SETUP, SETUP_SETTINGS};
+
+fn count(svg: &str, pattern: &str) -> usize {
+    svg.matches(pattern).count()
+}
+
+#[test]
+fn renders_any_turn() {
+    let record = GameRecord {
+        setup: serde_json::from_str(SETUP).unwrap(),
+        turns: vec![
+            vec![claim(0, 3, 5), claim(1, 1, 3)],
+            vec![claim(0, 5, 7), claim(1, 0, 1)],
+        ],
+    };
+
+    let svg = render::render_svg(&render::replay(&record, 0).unwrap());
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert_eq!(count(&svg, r#"class="river free""#), 12);
+    assert_eq!(count(&svg, r#"class="mine""#), 2);
+    assert_eq!(count(&svg, r#"class="site""#), 6);
+    assert!(svg.contains("Move 0 of 12"));
+
+    let svg = render::render_svg(&render::replay(&record, 1).unwrap());
+    assert_eq!(count(&svg, r#"class="river owned""#), 2);
+    assert_eq!(count(&svg, &format!(r#"stroke="{}""#, render::punter_color(0))), 1);
+    assert_eq!(count(&svg, &format!(r#"stroke="{}""#, render::punter_color(1))), 1);
+
+    // Asking for more turns than were played shows the end of the game
+    let svg = render::render_svg(&render::replay(&record, 10).unwrap());
+    assert_eq!(count(&svg, r#"class="river owned""#), 4);
+    assert!(svg.contains("Move 4 of 12"));
+}
+
+#[test]
+fn sites_use_map_coordinates() {
+    let record = GameRecord {
+        setup: serde_json::from_str(SETUP).unwrap(),
+        turns: vec![],
+    };
+    let svg = render::render_svg(&render::replay(&record, 0).unwrap());
+    // Sites 7 and 2 are at the far left of the map
+    for id in &[7, 2] {
+        let circle = svg.lines()
+            .find(|line| line.contains(&format!("<title>{}</title>", id)))
+            .unwrap();
+        assert!(circle.contains(r#"cx="40.0""#), "{}", circle);
+    }
+}
+
+#[test]
+fn maps_without_coordinates_and_options() {
+    let setup: Input = serde_json::from_str(SETUP_SETTINGS).unwrap();
+    let record = GameRecord {
+        setup,
+        turns: vec![vec![
+            claim(0, 0, 1),
+            option(2, 0, 1),
+        ]],
+    };
+    let svg = render::render_svg(&render::replay(&record, 1).unwrap());
+    assert_eq!(count(&svg, r#"class="river rented""#), 1);
+    assert_eq!(count(&svg, "<circle"), 4);
+}